@@ -1,9 +1,13 @@
 extern crate rmercury;
-use rmercury::{MercuryType, RMercuryBuilder};
+use rmercury::{MercuryType, RMercuryBuilder, RMercuryPlayerHandle, RMercuryUdpTransport};
+use std::net::SocketAddr;
 
 pub mod pong_lib;
 use pong_lib::*;
 
+/// The number of frames the local player's input is delayed by, matching the remote peer's.
+const LOCAL_INPUT_DELAY: usize = 3;
+
 fn main() {
     // Init GFX
     let mut pong_gfx = PongGfx::new();
@@ -13,9 +17,27 @@ fn main() {
 
     let mut builder =
         RMercuryBuilder::<GameInterface, GameInput, GameState>::new(&mut game_interface)
-            .with_type(MercuryType::Peer2Peer);
+            .with_type(MercuryType::Peer2Peer)
+            .with_local_input_delay(LOCAL_INPUT_DELAY);
+
+    // Usage: pong <local address> <remote address> [local player id]
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() >= 3 {
+        let local_address: SocketAddr = args[1].parse().unwrap();
+        let remote_address: SocketAddr = args[2].parse().unwrap();
+        let transport = RMercuryUdpTransport::bind(local_address, remote_address).unwrap();
+
+        builder = builder.with_remote_peer(transport);
+    }
+
+    // Without an explicit id, the players are assigned one during the handshake
+    if args.len() == 4 {
+        let local_player_id: usize = args[3].parse().unwrap();
+        builder = builder.with_local_player(Some(local_player_id), LOCAL_INPUT_DELAY);
+    }
 
     let mut r_mercury = builder.build();
+    let local_player: RMercuryPlayerHandle = 0;
 
     loop {
         {
@@ -30,7 +52,7 @@ fn main() {
                 .map(|i| GameInput::from_action(*i))
                 .collect();
 
-            r_mercury.add_local_input(&mut player_input);
+            r_mercury.add_local_input(local_player, &mut player_input);
             r_mercury.execute();
 
             let current_state = r_mercury.get_game_state();
@@ -4,7 +4,7 @@ use sdl2::{event::Event, keyboard::Keycode, pixels::Color, rect::Rect};
 use time::{Duration, Instant};
 
 extern crate rmercury;
-use rmercury::{RMercuryGameInterface, RMercuryInput};
+use rmercury::{fnv1a_hash, RMercuryError, RMercuryGameInterface, RMercuryInput};
 
 pub struct GameInterface {
     game_state: GameState,
@@ -222,6 +222,25 @@ impl RMercuryGameInterface<GameState, GameInput> for GameInterface {
             self.game_state.ball_velocity.y,
         );
     }
+
+    fn checksum(&self) -> u64 {
+        let coordinates = [
+            self.game_state.player1_coordinates,
+            self.game_state.player1_velocity,
+            self.game_state.player2_coordinates,
+            self.game_state.player2_velocity,
+            self.game_state.ball_coordinates,
+            self.game_state.ball_velocity,
+        ];
+
+        let mut bits = vec![];
+        for coordinate in coordinates.iter() {
+            bits.extend_from_slice(&coordinate.x.to_le_bytes());
+            bits.extend_from_slice(&coordinate.y.to_le_bytes());
+        }
+
+        return fnv1a_hash(&bits);
+    }
 }
 
 fn was_collision(
@@ -276,10 +295,19 @@ impl GameInput {
 
 impl RMercuryInput for GameInput {
     fn to_bits(&self) -> std::vec::Vec<u8> {
-        unimplemented!()
+        return vec![self.action as u8];
     }
-    fn from_bits(_: std::vec::Vec<u8>) -> Self {
-        unimplemented!()
+    fn from_bits(bytes: std::vec::Vec<u8>) -> Result<Self, RMercuryError> {
+        let action = match bytes.as_slice() {
+            [0] => Actions::MoveUp,
+            [1] => Actions::MoveDown,
+            [2] => Actions::MoveLeft,
+            [3] => Actions::MoveRight,
+            [4] => Actions::Quit,
+            _ => return Err(RMercuryError::InputMalformed),
+        };
+
+        return Ok(Self::from_action(action));
     }
     fn get_player_id(&self) -> usize {
         return self.player_id;
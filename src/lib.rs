@@ -1,11 +1,38 @@
 mod rmercury;
 mod rmercury_builder;
 mod rmercury_channel;
+mod rmercury_clock;
+mod rmercury_diagnostics;
+mod rmercury_error;
+mod rmercury_event;
 mod rmercury_game_interface;
 mod rmercury_input;
+mod rmercury_input_queue;
+mod rmercury_io;
+mod rmercury_packet;
+mod rmercury_prediction;
+mod rmercury_replay;
+mod rmercury_scheduler;
+mod rmercury_state_buffer;
+mod rmercury_sync;
+mod rmercury_sync_test;
+#[cfg(test)]
+mod rmercury_test_game;
+mod rmercury_time_sync;
+mod rmercury_transport;
 
 pub use crate::{
     rmercury::MercuryType, rmercury::RMercury, rmercury::RMercuryExecutionResults,
-    rmercury_builder::RMercuryBuilder, rmercury_game_interface::RMercuryGameInterface,
-    rmercury_input::RMercuryInput,
+    rmercury::RMercuryPlayerHandle, rmercury_builder::RMercuryBuilder,
+    rmercury_builder::RMercuryOptions, rmercury_clock::RMercuryClock,
+    rmercury_clock::RMercuryManualClock, rmercury_clock::RMercurySystemClock,
+    rmercury_diagnostics::RMercuryDiagnostics, rmercury_diagnostics::RMercuryDiagnosticsFrame,
+    rmercury_diagnostics::RMercuryDiagnosticsInput, rmercury_diagnostics::RMercuryDivergence,
+    rmercury_error::RMercuryError, rmercury_event::RMercuryEvent,
+    rmercury_game_interface::fnv1a_hash, rmercury_game_interface::RMercuryGameInterface,
+    rmercury_input::RMercuryInput, rmercury_packet::PROTOCOL_VERSION,
+    rmercury_prediction::RMercuryInputPredictor, rmercury_prediction::RMercuryRepeatLastInput,
+    rmercury_replay::RMercuryReplay, rmercury_sync::RMercurySessionConfig,
+    rmercury_sync_test::RMercurySyncTestMismatch, rmercury_transport::RMercuryMemoryTransport,
+    rmercury_transport::RMercuryTransport, rmercury_transport::RMercuryUdpTransport,
 };
@@ -1,9 +1,20 @@
 use super::*;
 
 use rmercury_channel::RChannelManager;
-use rmercury_input::{RMercuryInput, RMercuryInputWrapper};
-use time::{Duration, Instant};
-const MILLISECONDS_IN_SECOND: u64 = 1000;
+use rmercury_clock::RMercuryClock;
+use rmercury_diagnostics::{
+    RMercuryDiagnostics, RMercuryDiagnosticsFrame, RMercuryDiagnosticsInput,
+};
+use rmercury_event::RMercuryEvent;
+use rmercury_input::RMercuryInputWrapper;
+use rmercury_packet::RFrameChecksum;
+use rmercury_replay::RReplayWriter;
+use rmercury_scheduler::RFrameScheduler;
+use rmercury_state_buffer::RStateBuffer;
+use rmercury_sync_test::{RMercurySyncTestMismatch, RSyncTest};
+use std::io;
+use std::path::{Path, PathBuf};
+use time::Instant;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 /// The various states a RMercury session can be run in.
@@ -12,7 +23,7 @@ pub enum MercuryType {
     Peer2Peer,
     /// Start spectating a game in progress
     Spectator,
-    /// Start a session where each frame is rolled back, and then the serialized game state is checked against it. Sucessful runs means no non-deterministic behavior was introduced.
+    /// Start a session where each frame rolls back the configured number of frames, and then the checksum of each resimulated frame is checked against it. Sucessful runs means no non-deterministic behavior was introduced.
     SyncTest,
     /// Start a replay of a previous match
     Replay,
@@ -25,8 +36,17 @@ pub enum MercuryType {
 pub enum RMercuryExecutionResults {
     NotExecuted,
     Executed,
+    /// The simulation is too far ahead of the remote players and will not advance until their inputs arrive.
+    WaitingForRemote,
+    /// The handshake with the remote players is still in progress. The simulation starts once it completes.
+    Synchronizing,
+    /// The session can not run, such as when a remote player was configured differently.
+    Failed(RMercuryError),
 }
 
+/// Identifies a local player of a session. Handles are assigned in the order local players are added to the builder, starting at 0.
+pub type RMercuryPlayerHandle = usize;
+
 /// RMercury session object. This is the interface that your main loop must use.
 pub struct RMercury<'a, TGameInterface, TGameInput, TGameState>
 where
@@ -38,17 +58,53 @@ where
 {
     pub m_type: MercuryType,
     number_of_players: usize,
-    max_spectators: usize,
     sim_executions_per_second: usize,
-    local_input_frame_delay: usize,
+    /// The input delay of each local player, indexed by handle
+    local_input_delays: Vec<usize>,
+    max_prediction_frames: usize,
     game_interface: &'a mut TGameInterface,
     current_frame: usize,
     inputs: Vec<RMercuryInputWrapper<TGameInput>>,
-    last_confirmed_game_state: TGameState,
+    /// The game states at the start of each recent frame, used to roll back to a mispredicted frame.
+    saved_states: RStateBuffer<TGameState>,
+    /// Every frame before it has received the inputs of all players.
     last_confirmed_frame: usize,
-    frame_duration: time::Duration,
-    last_frame_execution: time::Instant,
+    /// The earliest frame that was simulated with predicted inputs that turned out to be wrong.
+    first_incorrect_frame: Option<usize>,
+    scheduler: RFrameScheduler,
+    clock: Box<dyn RMercuryClock>,
     channel_manager: RChannelManager<TGameInput>,
+    /// Events raised since the last poll.
+    events: Vec<RMercuryEvent>,
+    /// Whether the simulation has started.
+    running: bool,
+    /// The number of frames the session was last recommended to wait for the remote players.
+    frames_ahead: usize,
+    /// The checksums that resimulated frames are checked against in SyncTest sessions.
+    sync_test: RSyncTest<TGameState>,
+    /// The first frame a SyncTest session simulated differently after rolling it back.
+    sync_test_mismatch: Option<RMercurySyncTestMismatch>,
+    /// The number of frames between the checksums compared with the remote peers, or 0 if disabled.
+    desync_detection_interval: usize,
+    /// The checksums of simulated frames that are not confirmed yet, and may still change.
+    unconfirmed_checksums: Vec<RFrameChecksum>,
+    session_id: u32,
+    /// The directory diagnostics bundles are written to, or None if disabled.
+    diagnostics_directory: Option<PathBuf>,
+    /// The number of confirmed frames recorded for diagnostics bundles.
+    diagnostics_frames: usize,
+    /// The recorded frames that are not confirmed yet, and may still change, along with the serialized game state each was simulated from.
+    unconfirmed_diagnostics: Vec<(RMercuryDiagnosticsFrame, Vec<u8>)>,
+    /// The most recent recorded confirmed frames, oldest first, along with the serialized game state each was simulated from.
+    confirmed_diagnostics: Vec<(RMercuryDiagnosticsFrame, Vec<u8>)>,
+    /// The diagnostics bundle written for this session.
+    diagnostics_file: Option<PathBuf>,
+    /// Streams the inputs of confirmed frames, or None if not recording a replay.
+    replay: Option<RReplayWriter<TGameInput>>,
+    /// The inputs of simulated frames that are not confirmed yet, and may still change.
+    unconfirmed_replay_frames: Vec<(usize, Vec<TGameInput>)>,
+    /// The first error writing the replay ran into, which stops the recording.
+    replay_error: Option<io::Error>,
 }
 
 impl<'a, TGameInterface, TGameInput, TGameState>
@@ -60,43 +116,106 @@ where
     TGameInput: PartialEq,
     TGameState: Copy,
 {
-    /// Initialize a new RMercury session.
+    /// Initialize a new RMercury session with the options filled in by RMercuryBuilder.
     pub fn new(
-        m_type: MercuryType,
-        num_players: usize,
-        max_spectators: usize,
-        sim_executions_per_second: usize,
-        local_input_frame_delay: usize,
         game_interface: &'a mut TGameInterface,
+        options: RMercuryOptions<TGameInput>,
     ) -> Self {
-        let initial_game_state = game_interface.current_game_state();
+        let start = options.clock.now();
 
-        let frame_duration = Duration::milliseconds(
-            MILLISECONDS_IN_SECOND as i64 / sim_executions_per_second as i64,
-        );
+        let config = RMercurySessionConfig {
+            number_of_players: options.number_of_players,
+            sim_executions_per_second: options.sim_executions_per_second,
+            desync_detection_interval: options.desync_detection_interval,
+        };
 
-        let start = Instant::now();
+        let (replay, replay_error) = match options.replay_writer {
+            Some(replay_writer) => match RReplayWriter::new(
+                replay_writer,
+                options.session_id,
+                config,
+                game_interface.game_state_to_bits(),
+            ) {
+                Ok(replay) => (Some(replay), None),
+                Err(error) => (None, Some(error)),
+            },
+            None => (None, None),
+        };
+
+        let mut channel_manager = RChannelManager::new(
+            options.session_id,
+            options.transports,
+            options.input_predictor,
+            config,
+            options
+                .local_players
+                .iter()
+                .map(|(player_id, _)| *player_id)
+                .collect(),
+        );
+        channel_manager
+            .set_disconnect_timeout(options.disconnect_timeout, options.disconnect_notify_start);
 
         return Self {
-            m_type: m_type,
-            number_of_players: num_players,
-            max_spectators: max_spectators,
-            sim_executions_per_second: sim_executions_per_second,
-            local_input_frame_delay: local_input_frame_delay,
+            m_type: options.m_type,
+            number_of_players: options.number_of_players,
+            sim_executions_per_second: options.sim_executions_per_second,
+            local_input_delays: options
+                .local_players
+                .iter()
+                .map(|(_, input_delay)| *input_delay)
+                .collect(),
+            max_prediction_frames: options.max_prediction_frames,
             game_interface: game_interface,
             inputs: vec![],
             current_frame: 0,
             last_confirmed_frame: 0,
-            last_confirmed_game_state: initial_game_state,
-            frame_duration: frame_duration,
-            last_frame_execution: start,
-            channel_manager: RChannelManager::new(),
+            // The state at the start of the current frame is saved as well as every predicted or sync tested frame.
+            saved_states: RStateBuffer::new(
+                options.max_prediction_frames.max(options.sync_test_frames) + 1,
+            ),
+            first_incorrect_frame: None,
+            scheduler: RFrameScheduler::new(options.sim_executions_per_second, start),
+            clock: options.clock,
+            channel_manager: channel_manager,
+            events: vec![],
+            running: false,
+            frames_ahead: 0,
+            sync_test: RSyncTest::new(options.sync_test_frames),
+            sync_test_mismatch: None,
+            desync_detection_interval: options.desync_detection_interval,
+            unconfirmed_checksums: vec![],
+            session_id: options.session_id,
+            diagnostics_directory: options.diagnostics_directory,
+            diagnostics_frames: options.diagnostics_frames,
+            unconfirmed_diagnostics: vec![],
+            confirmed_diagnostics: vec![],
+            diagnostics_file: None,
+            replay: replay,
+            unconfirmed_replay_frames: vec![],
+            replay_error: replay_error,
         };
     }
 
-    pub fn get_local_player_id(&self) -> usize {
-        //TODO: implement based on connection time. E.g. if one player creates the host first, they get first character.
-        return 1;
+    /// Get the id of a local player. Either assigned through the builder, or negotiated with the remote players during the handshake. None until the handshake completes, or if the handle is unknown.
+    pub fn get_local_player_id(&self, handle: RMercuryPlayerHandle) -> Option<usize> {
+        return self
+            .channel_manager
+            .local_player_ids()?
+            .get(handle)
+            .copied();
+    }
+
+    /// Get the number of local players.
+    pub fn get_local_player_count(&self) -> usize {
+        return self.local_input_delays.len();
+    }
+
+    /// Sets the number of frames a local player's input is delayed by. Unknown handles are ignored.
+    pub fn set_local_input_delay(&mut self, handle: RMercuryPlayerHandle, input_delay: usize) {
+        if let Some(local_input_delay) = self.local_input_delays.get_mut(handle) {
+            *local_input_delay = input_delay;
+        }
     }
 
     /// Get a mutable reference to the game interface.
@@ -109,16 +228,24 @@ where
         return self.game_interface;
     }
 
-    /// Add the local player's input to the queue.
-    pub fn add_local_input(&mut self, inputs: &mut Vec<TGameInput>) {
-        let frame_to_execute = self.current_frame + self.local_input_frame_delay;
-        let local_player_id = self.get_local_player_id();
+    /// Add a local player's input to the queue. Inputs added before the player's id is known, after the player left, or for an unknown handle, are ignored.
+    pub fn add_local_input(&mut self, handle: RMercuryPlayerHandle, inputs: &mut Vec<TGameInput>) {
+        let local_player_id = match self.get_local_player_id(handle) {
+            Some(player_id) => player_id,
+            None => return,
+        };
+
+        if self.channel_manager.has_left(local_player_id) {
+            return;
+        }
+
+        let frame_to_execute = self.next_local_input_frame(handle);
 
         let mut wrapped_inputs: Vec<RMercuryInputWrapper<TGameInput>> = inputs
             .iter_mut()
             .map(|i| {
                 // Set the local inputs to execute in the future
-                let mut input = i.clone();
+                let mut input = *i;
                 input.set_player_id(local_player_id);
 
                 let wrapped_input = RMercuryInputWrapper::new(input, frame_to_execute);
@@ -127,81 +254,295 @@ where
             })
             .collect();
 
+        // Inputs added again for the same frame, such as while waiting for remote players, replace the previous ones.
+        self.inputs
+            .retain(|x| x.frame != frame_to_execute || x.get_player_id() != local_player_id);
+        self.channel_manager
+            .remove_local_input(frame_to_execute, local_player_id);
+
         self.channel_manager.queue_local_input(&wrapped_inputs);
 
         self.inputs.append(&mut wrapped_inputs);
     }
 
+    /// Get the frame the next input of a local player is added for.
+    fn next_local_input_frame(&self, handle: RMercuryPlayerHandle) -> usize {
+        // Frames that were already sent can't take new inputs, such as after the input delay was lowered.
+        return std::cmp::max(
+            self.current_frame + self.local_input_delays[handle],
+            self.channel_manager.next_local_frame(),
+        );
+    }
+
+    /// Remove a local player from the session, such as when they quit. From the frame their next input would have been added for, every peer simulates them with the game's disconnected inputs. Keep executing the session until the remote peers have been told. Unknown handles are ignored.
+    pub fn leave(&mut self, handle: RMercuryPlayerHandle) {
+        let local_player_id = match self.get_local_player_id(handle) {
+            Some(player_id) => player_id,
+            None => return,
+        };
+
+        let leave_frame = self.next_local_input_frame(handle);
+        for input in self.inputs.iter() {
+            if input.frame >= leave_frame && input.get_player_id() == local_player_id {
+                self.channel_manager
+                    .remove_local_input(input.frame, local_player_id);
+            }
+        }
+        self.inputs
+            .retain(|x| x.frame < leave_frame || x.get_player_id() != local_player_id);
+
+        self.channel_manager.leave(local_player_id, leave_frame);
+    }
+
     /// Whether RMercury is ready to execute. When true, ready to sync inputs and execute.
     pub fn ready_to_run(&self) -> bool {
-        let now = self.last_frame_execution - Instant::now();
-        let run_game_sim = self.frame_duration <= now;
+        return self.scheduler.is_frame_ready(self.clock.now());
+    }
+
+    /// Get how far the session is into the next frame, from 0 to 1. Renderers can use it to interpolate between the previous and current game state.
+    pub fn get_interpolation_alpha(&self) -> f32 {
+        return self.scheduler.alpha(self.clock.now());
+    }
+
+    /// Whether the handshake with every remote player has completed.
+    pub fn is_synchronized(&self) -> bool {
+        return self.channel_manager.is_synchronized();
+    }
+
+    /// Get the largest round trip time to any remote player, measured during the handshake.
+    pub fn get_round_trip_time(&self) -> Option<time::Duration> {
+        return self.channel_manager.round_trip_time();
+    }
+
+    /// Get the number of frames this session is running ahead of the slowest remote player. Frames are lengthened slightly until it reaches 0.
+    pub fn recommended_frame_delay(&self) -> usize {
+        return self.channel_manager.recommended_frame_delay();
+    }
+
+    /// Disconnect a peer, such as when kicking a player. Its players are simulated with the game's disconnected inputs from a frame agreed with the remaining peers.
+    pub fn disconnect_peer(&mut self, peer: usize) {
+        self.channel_manager.disconnect_peer(peer);
+    }
+
+    /// Get the first frame a SyncTest session simulated differently after rolling it back, along with both logged game states.
+    pub fn get_sync_test_mismatch(&self) -> Option<&RMercurySyncTestMismatch> {
+        return self.sync_test_mismatch.as_ref();
+    }
 
-        return run_game_sim;
+    /// Get the diagnostics bundle written when a desync or SyncTest mismatch was detected, if any.
+    pub fn get_diagnostics_file(&self) -> Option<&Path> {
+        return self.diagnostics_file.as_deref();
+    }
+
+    /// Write every simulated frame that is confirmed by now to the replay, then flush it and stop recording it. Frames that are not confirmed yet are left out. Returns the first error writing the replay ran into.
+    pub fn finish_replay(&mut self) -> io::Result<()> {
+        self.update_last_confirmed_frame();
+        self.write_confirmed_replay_frames();
+
+        if let Some(error) = self.replay_error.take() {
+            self.replay = None;
+            return Err(error);
+        }
+
+        return match self.replay.take() {
+            Some(mut replay) => replay.flush(),
+            None => Ok(()),
+        };
+    }
+
+    /// Take the events raised since the last call, oldest first.
+    pub fn poll_events(&mut self) -> Vec<RMercuryEvent> {
+        return std::mem::take(&mut self.events);
     }
 
     /// Execute RMercury. If enough time has passed, will execute the simulation. Otherwise will process outstanding network operations.
     pub fn execute(&mut self) -> RMercuryExecutionResults {
-        // Sync up network
-        let should_rollback;
-        {
-            let mut remote_inputs = self.channel_manager.execute();
-            remote_inputs.sort_by(|a, b| b.frame.cmp(&a.frame));
+        let now = self.clock.now();
+        self.scheduler.update(now);
+        let run_game_sim = self.scheduler.is_frame_ready(now);
+
+        let results = self.run(run_game_sim, now);
+        if results == RMercuryExecutionResults::Synchronizing {
+            // Start frame 0 fresh once synchronized, instead of catching up on the time spent synchronizing.
+            self.scheduler.reset(now);
+        }
+
+        if results == RMercuryExecutionResults::Executed {
+            self.scheduler.consume_frame();
 
-            let earliest_received_input = remote_inputs.first();
+            // Let the remote players catch up through slightly longer frames instead of a visible stall.
+            self.scheduler
+                .set_slow_down(self.channel_manager.recommended_frame_delay() > 0);
+        }
+
+        return results;
+    }
+
+    /// Sync the network and advance the simulation a single frame, regardless of how much time has passed. Lets bots, replays and servers own the timing of the session.
+    pub fn advance_frame(&mut self) -> RMercuryExecutionResults {
+        let now = self.clock.now();
+
+        return self.run(true, now);
+    }
+
+    /// Advance the simulation by the given number of frames, regardless of how much time has passed. Returns the number of frames advanced, which is less than requested if the session had to wait for remote players.
+    pub fn step(&mut self, frames: usize) -> usize {
+        for frame in 0..frames {
+            if self.advance_frame() != RMercuryExecutionResults::Executed {
+                return frame;
+            }
+        }
+
+        return frames;
+    }
+
+    /// Sync the network, then advance the simulation if requested.
+    fn run(&mut self, run_game_sim: bool, now: Instant) -> RMercuryExecutionResults {
+        if let Some(mismatch) = &self.sync_test_mismatch {
+            return RMercuryExecutionResults::Failed(RMercuryError::SyncTestMismatch {
+                frame: mismatch.frame,
+            });
+        }
+
+        if !self.channel_manager.is_synchronized() {
+            self.sync_network(now);
 
-            if earliest_received_input.is_some() {
-                let earliest_received_input = earliest_received_input.unwrap();
-                should_rollback = earliest_received_input.frame < self.current_frame;
-            } else {
-                should_rollback = false;
+            if let Some(error) = self
+                .channel_manager
+                .sync_error()
+                .or(self.channel_manager.send_error())
+            {
+                return RMercuryExecutionResults::Failed(error);
             }
 
-            self.inputs.append(&mut remote_inputs);
-            self.inputs.sort_by(|a, b| b.frame.cmp(&a.frame));
-            self.inputs.dedup();
+            return RMercuryExecutionResults::Synchronizing;
         }
 
-        let run_game_sim = self.ready_to_run();
+        if !self.running {
+            self.running = true;
+            self.events.push(RMercuryEvent::Running);
+        }
+
+        // Frames every player has confirmed are never simulated again.
+        self.update_last_confirmed_frame();
+
+        let waiting_for_remote =
+            self.current_frame - self.last_confirmed_frame >= self.max_prediction_frames;
+
+        if run_game_sim && !waiting_for_remote {
+            // No more local inputs will be added for this frame, so let the remote peers treat it as final.
+            let min_input_delay = self.local_input_delays.iter().min().copied().unwrap_or(0);
+            self.channel_manager
+                .confirm_local_frame(self.current_frame + min_input_delay);
+        }
+
+        // Sync up network
+        if let Some(incorrect_frame) = self
+            .sync_network(now)
+            .filter(|frame| *frame < self.current_frame)
+        {
+            // Frames that have not been simulated yet will use the correct inputs anyway.
+            self.first_incorrect_frame = match self.first_incorrect_frame {
+                Some(frame) if frame < incorrect_frame => Some(frame),
+                _ => Some(incorrect_frame),
+            };
+        }
+
+        // Packets that can't be serialized would never reach the remote peers, stalling them.
+        if let Some(error) = self.channel_manager.send_error() {
+            return RMercuryExecutionResults::Failed(error);
+        }
+
+        if run_game_sim && waiting_for_remote {
+            return RMercuryExecutionResults::WaitingForRemote;
+        }
 
         if run_game_sim {
-            if should_rollback {
-                self.game_interface
-                    .load_game_state(self.last_confirmed_game_state.clone());
+            if let Some(first_incorrect_frame) = self.first_incorrect_frame.take() {
+                // A prediction was wrong, so resimulate from the first frame that used it.
+                let game_state = self
+                    .saved_states
+                    .load(first_incorrect_frame)
+                    .expect("Rolled back further than the saved states!");
+                self.game_interface.load_game_state(game_state);
 
-                let mut rollback_frame = self.last_confirmed_frame;
+                let mut rollback_frame = first_incorrect_frame;
                 while rollback_frame < self.current_frame {
-                    let current_frame_inputs = self
-                        .inputs
-                        .iter()
-                        .filter(|x| x.frame == rollback_frame)
-                        .map(|x| x.input)
-                        .collect();
+                    self.simulate_frame(rollback_frame);
+                    rollback_frame += 1;
+                }
+            }
 
-                    self.game_interface.advance_frame(current_frame_inputs);
+            self.simulate_frame(self.current_frame);
 
-                    if rollback_frame == self.channel_manager.last_confirmed_frame() {
-                        // Save the confirmed frame
-                        let game_state = self.game_interface.current_game_state();
-                        self.last_confirmed_game_state = game_state;
-                    }
+            self.current_frame += 1;
+            self.channel_manager.advance_frame(self.current_frame);
 
-                    rollback_frame += 1;
+            if self.m_type == MercuryType::SyncTest {
+                if let Some(mismatch) = self.run_sync_test() {
+                    let frame = mismatch.frame;
+                    self.write_diagnostics(
+                        frame,
+                        format!(
+                            "SyncTest mismatch on frame {}\nExpected:\n{}\nActual:\n{}",
+                            frame, mismatch.expected, mismatch.actual
+                        ),
+                    );
+                    self.sync_test_mismatch = Some(mismatch);
+
+                    return RMercuryExecutionResults::Failed(RMercuryError::SyncTestMismatch {
+                        frame: frame,
+                    });
                 }
             }
 
-            let current_frame_inputs = self
-                .inputs
-                .iter()
-                .filter(|x| x.frame == self.current_frame)
-                .map(|x| x.input)
-                .collect();
+            let frames_ahead = self.channel_manager.recommended_frame_delay();
+            if frames_ahead != self.frames_ahead {
+                self.frames_ahead = frames_ahead;
 
-            self.game_interface.advance_frame(current_frame_inputs);
+                if frames_ahead > 0 {
+                    self.events.push(RMercuryEvent::TimeSync {
+                        frames_ahead: frames_ahead,
+                    });
+                }
+            }
 
-            // TODO: optimization: take all previously confirmed inputs, and persist to disk?
-            self.current_frame += 1;
-            self.last_frame_execution = Instant::now();
+            // Checksums of confirmed frames will never change, so the remote peers can compare them with their own.
+            let last_confirmed_frame = self.last_confirmed_frame;
+            self.unconfirmed_checksums
+                .sort_by_key(|checksum| checksum.frame);
+            for checksum in self.unconfirmed_checksums.iter() {
+                if checksum.frame < last_confirmed_frame {
+                    self.channel_manager.add_confirmed_checksum(*checksum);
+                }
+            }
+            self.unconfirmed_checksums
+                .retain(|checksum| checksum.frame >= last_confirmed_frame);
+
+            self.unconfirmed_diagnostics
+                .sort_by_key(|(frame, _)| frame.frame);
+            for recorded in self.unconfirmed_diagnostics.iter() {
+                if recorded.0.frame < last_confirmed_frame {
+                    self.confirmed_diagnostics.push(recorded.clone());
+                }
+            }
+            self.unconfirmed_diagnostics
+                .retain(|(frame, _)| frame.frame >= last_confirmed_frame);
+            if self.confirmed_diagnostics.len() > self.diagnostics_frames {
+                let excess = self.confirmed_diagnostics.len() - self.diagnostics_frames;
+                self.confirmed_diagnostics.drain(..excess);
+            }
+
+            self.write_confirmed_replay_frames();
+
+            // Inputs before the confirmed and sync tested frames are never needed again.
+            let last_confirmed_frame = self.last_confirmed_frame.min(
+                self.current_frame
+                    .saturating_sub(self.sync_test.check_distance()),
+            );
+            self.inputs.retain(|x| x.frame >= last_confirmed_frame);
+            self.channel_manager
+                .trim_remote_inputs(last_confirmed_frame);
 
             return RMercuryExecutionResults::Executed;
         }
@@ -209,6 +550,238 @@ where
         return RMercuryExecutionResults::NotExecuted;
     }
 
+    /// Roll back the sync test's check distance and simulate the frames again, checking each against the checksum after first simulating it. Returns the first frame that differs.
+    fn run_sync_test(&mut self) -> Option<RMercurySyncTestMismatch> {
+        let simulated_frame = self.current_frame - 1;
+        self.sync_test.record(
+            simulated_frame,
+            self.game_interface.checksum(),
+            self.game_interface.current_game_state(),
+        );
+
+        let rollback_frame = self
+            .current_frame
+            .saturating_sub(self.sync_test.check_distance());
+        if rollback_frame == self.current_frame {
+            return None;
+        }
+
+        let game_state = self
+            .saved_states
+            .load(rollback_frame)
+            .expect("Rolled back further than the saved states!");
+        self.game_interface.load_game_state(game_state);
+
+        for frame in rollback_frame..self.current_frame {
+            self.simulate_frame(frame);
+
+            if let Some(expected_state) =
+                self.sync_test.check(frame, self.game_interface.checksum())
+            {
+                // Only log the game states once they are known to differ.
+                let actual_state = self.game_interface.current_game_state();
+                let actual = self.game_interface.log_game_state();
+
+                self.game_interface.load_game_state(expected_state);
+                let expected = self.game_interface.log_game_state();
+                self.game_interface.load_game_state(actual_state);
+
+                return Some(RMercurySyncTestMismatch {
+                    frame: frame,
+                    expected: expected,
+                    actual: actual,
+                });
+            }
+        }
+
+        return None;
+    }
+
+    /// Move the last confirmed frame past every simulated frame that has received the inputs of all players.
+    fn update_last_confirmed_frame(&mut self) {
+        while self.last_confirmed_frame < self.current_frame
+            && self
+                .channel_manager
+                .is_frame_confirmed(self.last_confirmed_frame)
+        {
+            self.last_confirmed_frame += 1;
+        }
+    }
+
+    /// Stream the inputs of every newly confirmed frame to the replay, in order.
+    fn write_confirmed_replay_frames(&mut self) {
+        let replay = match &mut self.replay {
+            Some(replay) => replay,
+            None => return,
+        };
+
+        let mut result = Ok(());
+        while result.is_ok() && replay.next_frame() < self.last_confirmed_frame {
+            let next_frame = replay.next_frame();
+            let inputs = match self
+                .unconfirmed_replay_frames
+                .iter()
+                .find(|(frame, _)| *frame == next_frame)
+            {
+                Some((_, inputs)) => inputs,
+                None => break,
+            };
+
+            result = replay.write_frame(inputs);
+        }
+
+        let next_frame = replay.next_frame();
+        self.unconfirmed_replay_frames
+            .retain(|(frame, _)| *frame >= next_frame);
+
+        if let Err(error) = result {
+            self.replay = None;
+            self.replay_error = Some(error);
+        }
+    }
+
+    /// Write a diagnostics bundle of the recorded confirmed frames to the diagnostics directory, once per session.
+    fn write_diagnostics(&mut self, detected_frame: usize, reason: String) {
+        let directory = match &self.diagnostics_directory {
+            Some(directory) if self.diagnostics_file.is_none() => directory,
+            _ => return,
+        };
+
+        let local_player_ids = self.channel_manager.local_player_ids().unwrap_or(vec![]);
+        let file = directory.join(format!(
+            "rmercury-diagnostics-{}-{}-{}.bin",
+            self.session_id,
+            local_player_ids
+                .iter()
+                .map(|player_id| player_id.to_string())
+                .collect::<Vec<String>>()
+                .join("_"),
+            detected_frame
+        ));
+
+        let diagnostics = RMercuryDiagnostics {
+            session_id: self.session_id,
+            config: RMercurySessionConfig {
+                number_of_players: self.number_of_players,
+                sim_executions_per_second: self.sim_executions_per_second,
+                desync_detection_interval: self.desync_detection_interval,
+            },
+            local_player_ids: local_player_ids,
+            detected_frame: detected_frame,
+            reason: reason,
+            initial_state: self
+                .confirmed_diagnostics
+                .first()
+                .map(|(_, state)| state.clone())
+                .unwrap_or_default(),
+            frames: self
+                .confirmed_diagnostics
+                .iter()
+                .map(|(frame, _)| frame.clone())
+                .collect(),
+        };
+
+        // Failing to write the diagnostics must not stop the session.
+        if diagnostics.save(&file).is_ok() {
+            self.diagnostics_file = Some(file);
+        }
+    }
+
+    /// Sync up the network, collecting the events raised by the remote peers. Returns the earliest frame whose predicted inputs turned out to be wrong.
+    fn sync_network(&mut self, now: Instant) -> Option<usize> {
+        let first_incorrect_frame = self.channel_manager.execute(now);
+
+        let events = self.channel_manager.take_events();
+        for event in events.iter() {
+            if let RMercuryEvent::DesyncDetected { peer, frame } = event {
+                self.write_diagnostics(
+                    *frame,
+                    format!("Desync detected with peer {} on frame {}", peer, frame),
+                );
+            }
+        }
+        self.events.extend(events);
+
+        return first_incorrect_frame;
+    }
+
+    /// Save the game state, then advance the game a single frame using the local inputs and the received or predicted remote inputs.
+    fn simulate_frame(&mut self, frame: usize) {
+        self.saved_states
+            .save(frame, self.game_interface.current_game_state());
+
+        let recording_diagnostics = self.diagnostics_directory.is_some();
+
+        let mut frame_inputs: Vec<TGameInput> = self
+            .inputs
+            .iter()
+            .filter(|x| x.frame == frame)
+            .map(|x| x.input)
+            .collect();
+
+        frame_inputs.append(&mut self.channel_manager.remote_inputs(frame));
+
+        for player_id in self.channel_manager.disconnected_player_ids(frame) {
+            let mut inputs = self.game_interface.disconnected_inputs(player_id);
+            for input in inputs.iter_mut() {
+                input.set_player_id(player_id);
+            }
+
+            frame_inputs.append(&mut inputs);
+        }
+
+        // Remote inputs arrive in a different order on every peer, so every peer simulates them ordered by player.
+        frame_inputs.sort_by_key(|input| input.get_player_id());
+
+        let diagnostics_inputs: Vec<RMercuryDiagnosticsInput> = match recording_diagnostics {
+            true => frame_inputs
+                .iter()
+                .map(|input| RMercuryDiagnosticsInput {
+                    player_id: input.get_player_id(),
+                    payload: input.to_bits(),
+                })
+                .collect(),
+            false => vec![],
+        };
+        let diagnostics_state = match recording_diagnostics {
+            true => self.game_interface.game_state_to_bits(),
+            false => vec![],
+        };
+
+        if self.replay.is_some() {
+            // Frames simulated again after a rollback replace their previous inputs.
+            self.unconfirmed_replay_frames.retain(|(f, _)| *f != frame);
+            self.unconfirmed_replay_frames
+                .push((frame, frame_inputs.clone()));
+        }
+
+        self.game_interface.advance_frame(frame_inputs);
+
+        if recording_diagnostics {
+            self.unconfirmed_diagnostics
+                .retain(|(f, _)| f.frame != frame);
+            self.unconfirmed_diagnostics.push((
+                RMercuryDiagnosticsFrame::new(
+                    frame,
+                    diagnostics_inputs,
+                    self.game_interface.checksum(),
+                    self.game_interface.log_game_state(),
+                ),
+                diagnostics_state,
+            ));
+        }
+
+        if self.desync_detection_interval > 0 && frame % self.desync_detection_interval == 0 {
+            // Frames simulated again after a rollback replace their previous checksum.
+            self.unconfirmed_checksums
+                .retain(|checksum| checksum.frame != frame);
+            self.unconfirmed_checksums.push(RFrameChecksum {
+                frame: frame,
+                checksum: self.game_interface.checksum(),
+            });
+        }
+    }
+
     /// Get the current game tick.
     pub fn get_current_tick(&self) -> usize {
         return self.current_frame;
@@ -219,3 +792,1095 @@ where
         return self.game_interface.current_game_state();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmercury_test_game::*;
+    use time::Duration;
+
+    #[test]
+    fn rmercury_two_sessions_reach_identical_state() {
+        const FRAMES: usize = 30;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+
+        // Step both sessions in lockstep, one millisecond per frame
+        for frame in 0..FRAMES {
+            clock.advance(Duration::milliseconds(1));
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(1, (frame * 2) as u8)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(2, (frame * 3 + 1) as u8)]);
+
+            assert_eq!(RMercuryExecutionResults::Executed, session_a.execute());
+            assert_eq!(RMercuryExecutionResults::Executed, session_b.execute());
+        }
+
+        assert_eq!(FRAMES, session_a.get_current_tick());
+        assert_eq!(FRAMES, session_b.get_current_tick());
+        assert_eq!(session_a.get_game_state(), session_b.get_game_state());
+    }
+
+    #[test]
+    fn rmercury_inputs_are_simulated_in_player_order() {
+        const FRAMES: usize = 30;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+
+        // Each session receives its own inputs before the remote ones, and the game folds in the order it is given them
+        for frame in 0..FRAMES {
+            clock.advance(Duration::milliseconds(1));
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, frame as u8)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, frame as u8)]);
+            session_a.execute();
+            session_b.execute();
+        }
+
+        assert_eq!(true, session_a.get_game_state().order > 0);
+        assert_eq!(session_a.get_game_state(), session_b.get_game_state());
+    }
+
+    #[test]
+    fn rmercury_mispredicted_inputs_are_rolled_back() {
+        const FRAMES: usize = 40;
+        const CHANGING_FRAMES: usize = 20;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(3),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder.with_local_input_delay(0),
+        );
+
+        // Inputs change every frame, so predictions are wrong until they settle at the end
+        for frame in 0..FRAMES {
+            clock.advance(Duration::milliseconds(1));
+
+            let value = frame.min(CHANGING_FRAMES) as u8;
+            session_a.add_local_input(0, &mut vec![TestInput::new(1, value)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(2, value * 2)]);
+
+            assert_eq!(RMercuryExecutionResults::Executed, session_a.execute());
+            assert_eq!(RMercuryExecutionResults::Executed, session_b.execute());
+        }
+
+        assert_eq!(session_a.get_game_state(), session_b.get_game_state());
+    }
+
+    #[test]
+    fn rmercury_remote_too_far_behind_waits_for_remote() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |index, builder| {
+                let builder = builder.with_local_input_delay(0);
+                if index == 0 {
+                    return builder.with_max_prediction_frames(2);
+                }
+
+                return builder;
+            },
+        );
+
+        for _ in 0..2 {
+            clock.advance(Duration::milliseconds(1));
+            assert_eq!(RMercuryExecutionResults::Executed, session_a.execute());
+        }
+
+        clock.advance(Duration::milliseconds(1));
+        assert_eq!(
+            RMercuryExecutionResults::WaitingForRemote,
+            session_a.execute()
+        );
+        assert_eq!(2, session_a.get_current_tick());
+
+        // Once the remote player catches up a frame, the session can advance again
+        assert_eq!(RMercuryExecutionResults::Executed, session_b.execute());
+
+        // The first execution receives the remote inputs, the next one advances
+        session_a.execute();
+        assert_eq!(RMercuryExecutionResults::Executed, session_a.execute());
+        assert_eq!(3, session_a.get_current_tick());
+    }
+
+    #[test]
+    fn rmercury_step_advances_without_waiting() {
+        const FRAMES: usize = 30;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+
+        // The clock never advances, so only stepping moves the sessions
+        for frame in 0..FRAMES {
+            session_a.add_local_input(0, &mut vec![TestInput::new(1, frame as u8)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(2, frame as u8 + 1)]);
+
+            assert_eq!(1, session_a.step(1));
+            assert_eq!(1, session_b.step(1));
+        }
+
+        assert_eq!(FRAMES, session_a.get_current_tick());
+        assert_eq!(session_a.get_game_state(), session_b.get_game_state());
+    }
+
+    #[test]
+    fn rmercury_step_stops_when_waiting_for_remote() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session, _remote_session) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| {
+                builder
+                    .with_local_input_delay(0)
+                    .with_max_prediction_frames(4)
+            },
+        );
+
+        assert_eq!(4, session.step(10));
+        assert_eq!(4, session.get_current_tick());
+        assert_eq!(
+            RMercuryExecutionResults::WaitingForRemote,
+            session.advance_frame()
+        );
+    }
+
+    #[test]
+    fn rmercury_simulation_waits_for_handshake() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = session_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+
+        assert_eq!(
+            RMercuryExecutionResults::Synchronizing,
+            session_a.advance_frame()
+        );
+        assert_eq!(0, session_a.get_current_tick());
+
+        synchronize(&clock, &mut [&mut session_a, &mut session_b]);
+
+        assert_eq!(true, session_a.get_round_trip_time().is_some());
+        assert_eq!(
+            RMercuryExecutionResults::Executed,
+            session_a.advance_frame()
+        );
+    }
+
+    #[test]
+    fn rmercury_mismatched_config_fails() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = session_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |index, builder| builder.with_desync_detection_interval(2 + index * 2),
+        );
+
+        session_a.execute();
+        session_b.execute();
+        let actual = session_a.execute();
+
+        let config_a = RMercurySessionConfig {
+            number_of_players: 2,
+            sim_executions_per_second: 1000,
+            desync_detection_interval: 2,
+        };
+        let config_b = RMercurySessionConfig {
+            desync_detection_interval: 4,
+            ..config_a
+        };
+        let expected = RMercuryExecutionResults::Failed(RMercuryError::ConfigMismatch {
+            local: config_a,
+            remote: config_b,
+        });
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rmercury_different_input_delays_reach_identical_state() {
+        const FRAMES: usize = 30;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |index, builder| builder.with_local_player(None, 2 + index * 3),
+        );
+
+        for frame in 0..FRAMES {
+            clock.advance(Duration::milliseconds(1));
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, (frame * 2) as u8)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, (frame * 3 + 1) as u8)]);
+
+            assert_eq!(RMercuryExecutionResults::Executed, session_a.execute());
+            assert_eq!(RMercuryExecutionResults::Executed, session_b.execute());
+        }
+
+        assert_eq!(FRAMES, session_a.get_current_tick());
+        assert_eq!(FRAMES, session_b.get_current_tick());
+        assert_eq!(session_a.get_game_state(), session_b.get_game_state());
+    }
+
+    #[test]
+    fn rmercury_too_many_inputs_for_a_packet_fails() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+
+        // The inputs are sent once the frames before them are acknowledged
+        let mut results = vec![];
+        for _ in 0..10 {
+            clock.advance(Duration::milliseconds(1));
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, 1); 256]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, 1)]);
+            results.push(session_a.execute());
+            session_b.execute();
+        }
+
+        assert_eq!(
+            Some(&RMercuryExecutionResults::Failed(
+                RMercuryError::PacketFieldOverflow
+            )),
+            results.last()
+        );
+    }
+
+    #[test]
+    fn rmercury_negotiated_player_ids_are_unique() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = session_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+        assert_eq!(None, session_a.get_local_player_id(0));
+
+        synchronize(&clock, &mut [&mut session_a, &mut session_b]);
+
+        let mut player_ids = vec![
+            session_a.get_local_player_id(0).unwrap(),
+            session_b.get_local_player_id(0).unwrap(),
+        ];
+        player_ids.sort();
+        assert_eq!(vec![1, 2], player_ids);
+    }
+
+    #[test]
+    fn rmercury_explicit_player_ids_are_used() {
+        let clock = RMercuryManualClock::new();
+        let (transport_a, transport_b) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::zero());
+
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+
+        let mut session_a = test_builder(&mut game_a, &clock)
+            .with_local_player(Some(2), 3)
+            .with_remote_player(1, transport_a)
+            .build();
+        let mut session_b = test_builder(&mut game_b, &clock)
+            .with_local_player(Some(1), 3)
+            .with_remote_player(2, transport_b)
+            .build();
+        synchronize(&clock, &mut [&mut session_a, &mut session_b]);
+
+        assert_eq!(Some(2), session_a.get_local_player_id(0));
+        assert_eq!(Some(1), session_b.get_local_player_id(0));
+    }
+
+    #[test]
+    fn rmercury_unexpected_remote_player_id_fails() {
+        let clock = RMercuryManualClock::new();
+        let (transport_a, transport_b) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::zero());
+
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+
+        let mut session_a = test_builder(&mut game_a, &clock)
+            .with_remote_player(2, transport_a)
+            .build();
+        let mut session_b = test_builder(&mut game_b, &clock)
+            .with_local_player(Some(1), 3)
+            .with_remote_peer(transport_b)
+            .build();
+
+        session_a.execute();
+        session_b.execute();
+        let actual = session_a.execute();
+
+        let expected = RMercuryExecutionResults::Failed(RMercuryError::PlayerIdMismatch {
+            expected: 2,
+            actual: Some(1),
+        });
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rmercury_same_player_id_claimed_by_two_peers_fails() {
+        let clock = RMercuryManualClock::new();
+        let (transport_ab, transport_ba) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::zero());
+        let (transport_ac, transport_ca) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::zero());
+
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let mut game_c = TestGame::new();
+
+        // Sessions b and c never talk to each other, so only session a sees both claims
+        let mut session_a = test_builder(&mut game_a, &clock)
+            .with_players(3)
+            .with_remote_peer(transport_ab)
+            .with_remote_peer(transport_ac)
+            .build();
+        let mut session_b = test_builder(&mut game_b, &clock)
+            .with_players(3)
+            .with_local_player(Some(2), 3)
+            .with_remote_peer(transport_ba)
+            .build();
+        let mut session_c = test_builder(&mut game_c, &clock)
+            .with_players(3)
+            .with_local_player(Some(2), 3)
+            .with_remote_peer(transport_ca)
+            .build();
+
+        session_b.execute();
+        session_c.execute();
+        let actual = session_a.execute();
+
+        let expected = RMercuryExecutionResults::Failed(RMercuryError::DuplicatePlayerId(2));
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rmercury_more_peers_than_players_fails() {
+        let clock = RMercuryManualClock::new();
+        let (transport_ab, transport_ba) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::zero());
+        let (transport_ac, transport_ca) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::zero());
+
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let mut game_c = TestGame::new();
+
+        // Every handshake on its own fits in the two player session
+        let mut session_a = test_builder(&mut game_a, &clock)
+            .with_remote_peer(transport_ab)
+            .with_remote_peer(transport_ac)
+            .build();
+        let mut session_b = test_builder(&mut game_b, &clock)
+            .with_remote_peer(transport_ba)
+            .build();
+        let mut session_c = test_builder(&mut game_c, &clock)
+            .with_remote_peer(transport_ca)
+            .build();
+
+        session_b.execute();
+        session_c.execute();
+        let actual = session_a.execute();
+
+        let expected = RMercuryExecutionResults::Failed(RMercuryError::InvalidPlayerId(3));
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rmercury_multiple_local_players_reach_identical_state() {
+        const FRAMES: usize = 30;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |index, builder| {
+                let builder = builder.with_players(3);
+                if index == 0 {
+                    return builder
+                        .with_local_player(None, 3)
+                        .with_local_player(None, 1);
+                }
+
+                return builder;
+            },
+        );
+
+        let mut player_ids = vec![
+            session_a.get_local_player_id(0).unwrap(),
+            session_a.get_local_player_id(1).unwrap(),
+            session_b.get_local_player_id(0).unwrap(),
+        ];
+        assert_eq!(true, player_ids[0] < player_ids[1]);
+        player_ids.sort();
+        assert_eq!(vec![1, 2, 3], player_ids);
+
+        for frame in 0..FRAMES {
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, frame as u8)]);
+            session_a.add_local_input(1, &mut vec![TestInput::new(0, frame as u8 * 2)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, frame as u8 * 3)]);
+
+            session_a.step(1);
+            session_b.step(1);
+            clock.advance(Duration::milliseconds(1));
+        }
+
+        assert_eq!(FRAMES, session_a.get_current_tick());
+        assert_eq!(session_a.get_game_state(), session_b.get_game_state());
+    }
+
+    #[test]
+    fn rmercury_local_input_delay_is_per_player() {
+        let mut game = TestGame::new();
+        let mut builder = RMercuryBuilder::new(&mut game)
+            .with_local_player(None, 0)
+            .with_local_player(None, 2);
+
+        let mut session = builder.build();
+
+        session.advance_frame();
+        session.add_local_input(0, &mut vec![TestInput::new(0, 0)]);
+        session.add_local_input(1, &mut vec![TestInput::new(0, 0)]);
+        session.step(3);
+
+        // Each input adds the frame it was simulated on to the total
+        let state = session.get_game_state();
+        assert_eq!(4, state.frame);
+        assert_eq!(2 + 4, state.total);
+    }
+
+    #[test]
+    fn rmercury_set_local_input_delay_changes_player_delay() {
+        let mut game = TestGame::new();
+        let mut builder = RMercuryBuilder::new(&mut game)
+            .with_local_player(None, 0)
+            .with_local_player(None, 0);
+
+        let mut session = builder.build();
+        session.set_local_input_delay(1, 2);
+
+        session.add_local_input(0, &mut vec![TestInput::new(0, 0)]);
+        session.add_local_input(1, &mut vec![TestInput::new(0, 0)]);
+        session.step(3);
+
+        let state = session.get_game_state();
+        assert_eq!(3, state.frame);
+        assert_eq!(1 + 3, state.total);
+    }
+
+    #[test]
+    fn rmercury_poll_events_reports_handshake_progress() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, _session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+        session_a.advance_frame();
+
+        let mut expected = vec![RMercuryEvent::ConnectedToPeer { peer: 0 }];
+        for count in 1..=5 {
+            expected.push(RMercuryEvent::SynchronizingWithPeer {
+                peer: 0,
+                count: count,
+                total: 5,
+            });
+        }
+        expected.push(RMercuryEvent::SynchronizedWithPeer { peer: 0 });
+        expected.push(RMercuryEvent::Running);
+
+        assert_eq!(expected, session_a.poll_events());
+        assert_eq!(true, session_a.poll_events().is_empty());
+    }
+
+    #[test]
+    fn rmercury_poll_events_running_ahead_reports_time_sync() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+
+        // Session a tries to run three times as fast as session b
+        for _ in 0..60 {
+            session_a.step(3);
+            session_b.step(1);
+            clock.advance(Duration::milliseconds(1));
+        }
+
+        let time_syncs = session_a
+            .poll_events()
+            .into_iter()
+            .filter(|event| match event {
+                RMercuryEvent::TimeSync { frames_ahead } => *frames_ahead > 0,
+                _ => false,
+            })
+            .count();
+        assert_eq!(true, time_syncs > 0);
+    }
+
+    #[test]
+    fn rmercury_silent_peer_is_interrupted_then_resumed() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| {
+                builder
+                    .with_disconnect_timeout(5000)
+                    .with_disconnect_notify_start(750)
+            },
+        );
+
+        for _ in 0..3 {
+            clock.advance(Duration::milliseconds(1));
+            session_a.execute();
+            session_b.execute();
+        }
+        session_a.execute();
+        session_a.poll_events();
+
+        // Session b stops sending
+        clock.advance(Duration::milliseconds(800));
+        session_a.execute();
+        assert_eq!(
+            true,
+            session_a
+                .poll_events()
+                .contains(&RMercuryEvent::ConnectionInterrupted {
+                    peer: 0,
+                    disconnect_timeout: Some(Duration::milliseconds(4200)),
+                })
+        );
+
+        session_b.execute();
+        session_a.execute();
+        assert_eq!(
+            true,
+            session_a
+                .poll_events()
+                .contains(&RMercuryEvent::ConnectionResumed { peer: 0 })
+        );
+    }
+
+    #[test]
+    fn rmercury_silent_peer_without_disconnect_timeout_is_interrupted_then_resumed() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder.with_disconnect_notify_start(750),
+        );
+
+        for _ in 0..3 {
+            clock.advance(Duration::milliseconds(1));
+            session_a.execute();
+            session_b.execute();
+        }
+        session_a.execute();
+        session_a.poll_events();
+
+        // Session b stops sending
+        clock.advance(Duration::milliseconds(800));
+        session_a.execute();
+        assert_eq!(
+            true,
+            session_a
+                .poll_events()
+                .contains(&RMercuryEvent::ConnectionInterrupted {
+                    peer: 0,
+                    disconnect_timeout: None,
+                })
+        );
+
+        session_b.execute();
+        session_a.execute();
+        assert_eq!(
+            true,
+            session_a
+                .poll_events()
+                .contains(&RMercuryEvent::ConnectionResumed { peer: 0 })
+        );
+    }
+
+    #[test]
+    fn rmercury_timed_out_peer_is_disconnected() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, _session_b) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder.with_disconnect_timeout(1000),
+        );
+        session_a.poll_events();
+
+        // Session b is never heard from again
+        clock.advance(Duration::milliseconds(1000));
+        session_a.advance_frame();
+        assert_eq!(
+            true,
+            session_a
+                .poll_events()
+                .contains(&RMercuryEvent::DisconnectedFromPeer { peer: 0 })
+        );
+
+        assert_eq!(20, session_a.step(20));
+    }
+
+    #[test]
+    fn rmercury_silent_peer_without_disconnect_timeout_stays_connected() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, _session_b) = synchronized_pair(
+            &clock,
+            Duration::zero(),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+        session_a.poll_events();
+
+        // Session b is never heard from again
+        clock.advance(Duration::seconds(60));
+        session_a.advance_frame();
+        assert_eq!(
+            false,
+            session_a
+                .poll_events()
+                .contains(&RMercuryEvent::DisconnectedFromPeer { peer: 0 })
+        );
+
+        // Session a waits for session b's inputs instead
+        assert_eq!(true, session_a.step(20) < 20);
+    }
+
+    #[test]
+    fn rmercury_disconnected_peer_is_simulated_with_disconnected_inputs() {
+        const FRAMES: usize = 40;
+        const DISCONNECT_FRAME: usize = 10;
+
+        let clock = RMercuryManualClock::new();
+        let (transport_ab, transport_ba) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::milliseconds(1));
+        let (transport_ac, transport_ca) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::milliseconds(1));
+        let (transport_bc, transport_cb) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::milliseconds(1));
+
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let mut game_c = TestGame::new();
+
+        let mut session_a = test_builder(&mut game_a, &clock)
+            .with_players(3)
+            .with_remote_peer(transport_ab)
+            .with_remote_peer(transport_ac)
+            .build();
+        let mut session_b = test_builder(&mut game_b, &clock)
+            .with_players(3)
+            .with_remote_peer(transport_ba)
+            .with_remote_peer(transport_bc)
+            .build();
+        let mut session_c = test_builder(&mut game_c, &clock)
+            .with_players(3)
+            .with_remote_peer(transport_ca)
+            .with_remote_peer(transport_cb)
+            .build();
+        synchronize(
+            &clock,
+            &mut [&mut session_a, &mut session_b, &mut session_c],
+        );
+        session_b.poll_events();
+
+        for _ in 0..200 {
+            clock.advance(Duration::milliseconds(1));
+
+            if session_a.get_current_tick() == DISCONNECT_FRAME {
+                // Session c is kicked by session a, and never heard from again
+                session_a.disconnect_peer(1);
+            }
+
+            if session_a.get_current_tick() < FRAMES {
+                session_a.add_local_input(0, &mut vec![TestInput::new(0, 1)]);
+                session_a.execute();
+            }
+            if session_b.get_current_tick() < FRAMES {
+                session_b.add_local_input(0, &mut vec![TestInput::new(0, 2)]);
+                session_b.execute();
+            }
+            if session_a.get_current_tick() < DISCONNECT_FRAME {
+                session_c.add_local_input(0, &mut vec![TestInput::new(0, 5)]);
+                session_c.execute();
+            }
+        }
+
+        assert_eq!(
+            true,
+            session_b
+                .poll_events()
+                .contains(&RMercuryEvent::DisconnectedFromPeer { peer: 1 })
+        );
+        assert_eq!(FRAMES, session_a.get_current_tick());
+        assert_eq!(FRAMES, session_b.get_current_tick());
+        assert_eq!(session_a.get_game_state(), session_b.get_game_state());
+    }
+
+    #[test]
+    fn rmercury_local_player_leaving_is_simulated_with_disconnected_inputs() {
+        const FRAMES: usize = 40;
+        const LEAVE_FRAME: usize = 10;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |index, builder| {
+                let builder = builder.with_players(3);
+                if index == 0 {
+                    return builder
+                        .with_local_player(None, 3)
+                        .with_local_player(None, 3);
+                }
+
+                return builder;
+            },
+        );
+        let leaving_player_id = session_a.get_local_player_id(1).unwrap();
+
+        let mut events_a = vec![];
+        let mut events_b = vec![];
+        for _ in 0..200 {
+            clock.advance(Duration::milliseconds(1));
+
+            if session_a.get_current_tick() == LEAVE_FRAME {
+                session_a.leave(1);
+            }
+
+            if session_a.get_current_tick() < FRAMES {
+                session_a.add_local_input(0, &mut vec![TestInput::new(0, 1)]);
+                session_a.add_local_input(1, &mut vec![TestInput::new(0, 2)]);
+                session_a.execute();
+            }
+            if session_b.get_current_tick() < FRAMES {
+                session_b.add_local_input(0, &mut vec![TestInput::new(0, 5)]);
+                session_b.execute();
+            }
+
+            events_a.append(&mut session_a.poll_events());
+            events_b.append(&mut session_b.poll_events());
+        }
+
+        let expected = RMercuryEvent::PlayerDisconnected {
+            player_id: leaving_player_id,
+            frame: LEAVE_FRAME + 3,
+        };
+        assert_eq!(true, events_a.contains(&expected));
+        assert_eq!(true, events_b.contains(&expected));
+        assert_eq!(
+            false,
+            events_b.contains(&RMercuryEvent::DisconnectedFromPeer { peer: 0 })
+        );
+        assert_eq!(FRAMES, session_a.get_current_tick());
+        assert_eq!(FRAMES, session_b.get_current_tick());
+        assert_eq!(session_a.get_game_state(), session_b.get_game_state());
+    }
+
+    #[test]
+    fn rmercury_peer_whose_players_left_is_not_waited_for() {
+        const FRAMES: usize = 40;
+        const LEAVE_FRAME: usize = 10;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+        let leaving_player_id = session_a.get_local_player_id(0).unwrap();
+
+        while session_a.get_current_tick() < LEAVE_FRAME {
+            clock.advance(Duration::milliseconds(1));
+            session_a.execute();
+            session_b.execute();
+        }
+
+        // Session a leaves, then stops once session b has been told
+        session_a.leave(0);
+        for _ in 0..10 {
+            clock.advance(Duration::milliseconds(1));
+            session_a.execute();
+            session_b.execute();
+        }
+
+        let frames = FRAMES - session_b.get_current_tick();
+        assert_eq!(frames, session_b.step(frames));
+        assert_eq!(
+            true,
+            session_b
+                .poll_events()
+                .contains(&RMercuryEvent::PlayerDisconnected {
+                    player_id: leaving_player_id,
+                    frame: LEAVE_FRAME + 3,
+                })
+        );
+    }
+
+    #[test]
+    fn rmercury_identical_sessions_detect_no_desync() {
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder.with_desync_detection_interval(5),
+        );
+
+        let mut events = vec![];
+        for frame in 0..60 {
+            clock.advance(Duration::milliseconds(1));
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, (frame * 2) as u8)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, (frame * 3 + 1) as u8)]);
+            session_a.execute();
+            session_b.execute();
+            events.append(&mut session_a.poll_events());
+            events.append(&mut session_b.poll_events());
+        }
+
+        let desyncs: Vec<&RMercuryEvent> = events
+            .iter()
+            .filter(|event| matches!(event, RMercuryEvent::DesyncDetected { .. }))
+            .collect();
+
+        assert_eq!(true, desyncs.is_empty());
+    }
+
+    #[test]
+    fn rmercury_diverged_sessions_detect_desync() {
+        const DIVERGE_FRAME: usize = 20;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder.with_desync_detection_interval(5),
+        );
+
+        let mut events = vec![];
+        for _ in 0..60 {
+            clock.advance(Duration::milliseconds(1));
+
+            if session_b.get_current_tick() == DIVERGE_FRAME {
+                // Something outside of the simulation changes the game state of session b
+                session_b.get_game_interface_mut().state.total += 1;
+            }
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, 1)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, 2)]);
+            session_a.execute();
+            session_b.execute();
+            events.append(&mut session_a.poll_events());
+        }
+
+        let first_desync = events.iter().find_map(|event| match event {
+            RMercuryEvent::DesyncDetected { peer, frame } => Some((*peer, *frame)),
+            _ => None,
+        });
+
+        assert_eq!(Some((0, DIVERGE_FRAME)), first_desync);
+    }
+
+    #[test]
+    fn rmercury_diverged_sessions_without_desync_detection_raise_nothing() {
+        const DIVERGE_FRAME: usize = 20;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder,
+        );
+
+        let mut events = vec![];
+        for _ in 0..60 {
+            clock.advance(Duration::milliseconds(1));
+
+            if session_b.get_current_tick() == DIVERGE_FRAME {
+                session_b.get_game_interface_mut().state.total += 1;
+            }
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, 1)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, 2)]);
+            session_a.execute();
+            session_b.execute();
+            events.append(&mut session_a.poll_events());
+        }
+
+        let desyncs = events
+            .iter()
+            .filter(|event| matches!(event, RMercuryEvent::DesyncDetected { .. }))
+            .count();
+        assert_eq!(0, desyncs);
+        assert_eq!(true, session_a.get_current_tick() > DIVERGE_FRAME);
+    }
+
+    #[test]
+    fn rmercury_transient_desync_between_sparse_packets_is_detected() {
+        const DIVERGE_FRAME: usize = 19;
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| builder.with_desync_detection_interval(1),
+        );
+
+        let mut events = vec![];
+        for time in 0..60 {
+            clock.advance(Duration::milliseconds(1));
+
+            // Session a only runs every 4 milliseconds, so session b confirms several frames per packet
+            if time % 4 == 3 {
+                for _ in 0..4 {
+                    session_a.add_local_input(0, &mut vec![TestInput::new(0, 1)]);
+                    session_a.execute();
+                }
+            }
+
+            // The game state of session b only differs for a single frame
+            let tick = session_b.get_current_tick();
+            if tick == DIVERGE_FRAME {
+                session_b.get_game_interface_mut().state.total += 1;
+            }
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, 2)]);
+            session_b.execute();
+            if tick == DIVERGE_FRAME && session_b.get_current_tick() > tick {
+                session_b.get_game_interface_mut().state.total -= 1;
+            }
+
+            events.append(&mut session_a.poll_events());
+        }
+
+        let desyncs: Vec<usize> = events
+            .iter()
+            .filter_map(|event| match event {
+                RMercuryEvent::DesyncDetected { frame, .. } => Some(*frame),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(vec![DIVERGE_FRAME], desyncs);
+    }
+}
@@ -1,5 +1,53 @@
 use super::*;
+use std::io::Write;
 use std::marker::PhantomData;
+use std::path::PathBuf;
+use time::Duration;
+
+/// The settings of a RMercury session, filled in by RMercuryBuilder.
+pub struct RMercuryOptions<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    /// The type of session to build
+    pub m_type: MercuryType,
+    /// The number of players for the session
+    pub number_of_players: usize,
+    /// The number of spectators to allow
+    pub number_of_spectators: usize,
+    /// The number of frames to delay the input of the local player added when none are set
+    pub local_input_delay: usize,
+    /// The maximum number of frames to simulate with predicted remote inputs
+    pub max_prediction_frames: usize,
+    /// The number of frames a SyncTest session rolls back after each frame
+    pub sync_test_frames: usize,
+    /// The number of frames between the checksums compared with the remote peers, or 0 if disabled
+    pub desync_detection_interval: usize,
+    /// The number of times to advance the game per second
+    pub sim_executions_per_second: usize,
+    /// The id of the session, used to discard packets from other sessions
+    pub session_id: u32,
+    /// The ids of the local players, negotiated during the handshake if not set, along with the number of frames to delay their input
+    pub local_players: Vec<(Option<usize>, usize)>,
+    /// The transports to the remote peers, along with their player ids if known
+    pub transports: Vec<(Option<usize>, Box<dyn RMercuryTransport>)>,
+    /// The strategy used to predict remote inputs that have not arrived yet
+    pub input_predictor: Box<dyn RMercuryInputPredictor<TGameInput>>,
+    /// The clock used to pace the simulation
+    pub clock: Box<dyn RMercuryClock>,
+    /// How long a remote peer may be silent before it is disconnected, if ever
+    pub disconnect_timeout: Option<Duration>,
+    /// How long a remote peer may be silent before the interruption is notified
+    pub disconnect_notify_start: Duration,
+    /// The directory diagnostics bundles are written to
+    pub diagnostics_directory: Option<PathBuf>,
+    /// The number of confirmed frames recorded for diagnostics bundles
+    pub diagnostics_frames: usize,
+    /// The writer the confirmed inputs are streamed to as a replay
+    pub replay_writer: Option<Box<dyn Write>>,
+}
 
 /// A builder for starting an RMercury session. Requires a game interface to execute game logic, the type for inputs, as well as the type for game states.
 pub struct RMercuryBuilder<'a, TGameInterface, TGameInput, TGameState>
@@ -10,28 +58,23 @@ where
     TGameInput: PartialEq,
     TGameState: Copy,
 {
-    /// The type of session to build
-    m_type: MercuryType,
-    /// The number of players for the session
-    number_of_players: usize,
-    /// The number of spectators to allow
-    number_of_spectators: usize,
-    /// The number of frames to delay local input
-    local_input_delay: usize,
-    /// The number of times to advance the game per second    
-    sim_executions_per_second: usize,
-    /// The game interface RMercury will interact with.
-    game_interface: &'a mut TGameInterface,
-    /// Whether the builder was consumed or not.
-    was_built: bool,
-    phantom_input: PhantomData<&'a TGameInput>,
+    /// The game interface RMercury will interact with, until it is handed to the built session.
+    game_interface: Option<&'a mut TGameInterface>,
+    /// The settings of the session to build, taken once built.
+    options: Option<RMercuryOptions<TGameInput>>,
     phantom_state: PhantomData<TGameState>,
 }
 
 const DEFAULT_NUM_PLAYERS: usize = 2;
 const DEFAULT_NUM_SPECTATORS: usize = 4;
 const DEFAULT_LOCAL_INPUT_DELAY: usize = 3;
+const DEFAULT_MAX_PREDICTION_FRAMES: usize = 8;
+const DEFAULT_SYNC_TEST_FRAMES: usize = 1;
+const DEFAULT_DESYNC_DETECTION_INTERVAL: usize = 0;
 const DEFAULT_SIM_EXECUTIONS_PER_SECOND: usize = 60;
+const DEFAULT_SESSION_ID: u32 = 0;
+const DEFAULT_DISCONNECT_NOTIFY_START: i64 = 750;
+const DEFAULT_DIAGNOSTICS_FRAMES: usize = 60;
 
 impl<'a, TGameInterface, TGameInput, TGameState>
     RMercuryBuilder<'a, TGameInterface, TGameInput, TGameState>
@@ -45,21 +88,39 @@ where
     /// Create a new RMercuryBuilder to initialize the network settings.
     pub fn new(game_interface: &'a mut TGameInterface) -> Self {
         return Self {
-            m_type: MercuryType::Peer2Peer,
-            number_of_players: DEFAULT_NUM_PLAYERS,
-            number_of_spectators: DEFAULT_NUM_SPECTATORS,
-            local_input_delay: DEFAULT_LOCAL_INPUT_DELAY,
-            sim_executions_per_second: DEFAULT_SIM_EXECUTIONS_PER_SECOND,
-            game_interface: game_interface,
-            was_built: false,
-            phantom_input: PhantomData,
+            game_interface: Some(game_interface),
+            options: Some(RMercuryOptions {
+                m_type: MercuryType::Peer2Peer,
+                number_of_players: DEFAULT_NUM_PLAYERS,
+                number_of_spectators: DEFAULT_NUM_SPECTATORS,
+                local_input_delay: DEFAULT_LOCAL_INPUT_DELAY,
+                max_prediction_frames: DEFAULT_MAX_PREDICTION_FRAMES,
+                sync_test_frames: DEFAULT_SYNC_TEST_FRAMES,
+                desync_detection_interval: DEFAULT_DESYNC_DETECTION_INTERVAL,
+                sim_executions_per_second: DEFAULT_SIM_EXECUTIONS_PER_SECOND,
+                session_id: DEFAULT_SESSION_ID,
+                local_players: vec![],
+                transports: vec![],
+                input_predictor: Box::new(RMercuryRepeatLastInput),
+                clock: Box::new(RMercurySystemClock),
+                disconnect_timeout: None,
+                disconnect_notify_start: Duration::milliseconds(DEFAULT_DISCONNECT_NOTIFY_START),
+                diagnostics_directory: None,
+                diagnostics_frames: DEFAULT_DIAGNOSTICS_FRAMES,
+                replay_writer: None,
+            }),
             phantom_state: PhantomData,
         };
     }
 
+    /// The settings of the session to build.
+    fn options(&mut self) -> &mut RMercuryOptions<TGameInput> {
+        return self.options.as_mut().expect("Builder already consumed!");
+    }
+
     /// Sets the type of the network session.
     pub fn with_type(mut self, m_type: MercuryType) -> Self {
-        self.m_type = m_type;
+        self.options().m_type = m_type;
 
         return self;
     }
@@ -67,46 +128,153 @@ where
     /// Sets the number of players for the network session.
     pub fn with_players(mut self, num_players: usize) -> Self {
         if num_players > 0 {
-            self.number_of_players = num_players;
+            self.options().number_of_players = num_players;
         }
         return self;
     }
 
     /// Sets the number of spectators allowed for the network session.
     pub fn with_spectators(mut self, num_spectators: usize) -> Self {
-        self.number_of_spectators = num_spectators;
+        self.options().number_of_spectators = num_spectators;
         return self;
     }
 
-    /// Sets the number of players for the network session.
+    /// Sets the number of frames to delay the input of the local player added when no local players are set.
     pub fn with_local_input_delay(mut self, local_input_delay: usize) -> Self {
-        self.local_input_delay = local_input_delay;
+        self.options().local_input_delay = local_input_delay;
+        return self;
+    }
+
+    /// Sets the maximum number of frames to simulate ahead of the remote players' inputs. Once reached, the session waits for the remote players. Minimum of 1.
+    pub fn with_max_prediction_frames(mut self, max_prediction_frames: usize) -> Self {
+        if max_prediction_frames > 0 {
+            self.options().max_prediction_frames = max_prediction_frames;
+        }
         return self;
     }
 
     /// Sets the number of frames to execute per second for the simulation. Minimum of 1.
     pub fn with_sim_executions_per_second(mut self, hz: usize) -> Self {
         if hz > 0 {
-            self.sim_executions_per_second = hz;
+            self.options().sim_executions_per_second = hz;
+        }
+        return self;
+    }
+
+    /// Adds a local player to the network session, whose input is delayed by the given number of frames. Its handle is the number of local players added before it. Without an id, one is negotiated with the remote peers during the handshake. Without any local players, a single one with a negotiated id and the local input delay is added.
+    pub fn with_local_player(mut self, player_id: Option<usize>, input_delay: usize) -> Self {
+        self.options().local_players.push((player_id, input_delay));
+
+        return self;
+    }
+
+    /// Adds a remote peer to the network session. The transport will be used to exchange inputs with the peer, whose player id is negotiated during the handshake.
+    pub fn with_remote_peer<TTransport>(mut self, transport: TTransport) -> Self
+    where
+        TTransport: RMercuryTransport + 'static,
+    {
+        self.options().transports.push((None, Box::new(transport)));
+
+        return self;
+    }
+
+    /// Adds a remote player with a known id to the network session. The handshake fails if the peer claims a different id.
+    pub fn with_remote_player<TTransport>(mut self, player_id: usize, transport: TTransport) -> Self
+    where
+        TTransport: RMercuryTransport + 'static,
+    {
+        self.options()
+            .transports
+            .push((Some(player_id), Box::new(transport)));
+
+        return self;
+    }
+
+    /// Sets the id of the network session. Packets from peers using a different session id are ignored.
+    pub fn with_session_id(mut self, session_id: u32) -> Self {
+        self.options().session_id = session_id;
+        return self;
+    }
+
+    /// Sets the strategy used to predict remote inputs that have not arrived yet. Defaults to repeating the last received inputs.
+    pub fn with_input_predictor<TPredictor>(mut self, input_predictor: TPredictor) -> Self
+    where
+        TPredictor: RMercuryInputPredictor<TGameInput> + 'static,
+    {
+        self.options().input_predictor = Box::new(input_predictor);
+
+        return self;
+    }
+
+    /// Sets the clock used to pace the simulation. Defaults to the system clock.
+    pub fn with_clock<TClock>(mut self, clock: TClock) -> Self
+    where
+        TClock: RMercuryClock + 'static,
+    {
+        self.options().clock = Box::new(clock);
+
+        return self;
+    }
+
+    /// Sets the number of milliseconds a remote peer may be silent before it is disconnected. The simulation then continues without inputs for its players. Peers are never disconnected unless set, and 0 disables disconnecting again.
+    pub fn with_disconnect_timeout(mut self, milliseconds: usize) -> Self {
+        self.options().disconnect_timeout = if milliseconds > 0 {
+            Some(Duration::milliseconds(milliseconds as i64))
+        } else {
+            None
+        };
+        return self;
+    }
+
+    /// Sets the number of frames a SyncTest session rolls back and simulates again after each frame. Minimum of 1.
+    pub fn with_sync_test_frames(mut self, sync_test_frames: usize) -> Self {
+        if sync_test_frames > 0 {
+            self.options().sync_test_frames = sync_test_frames;
         }
         return self;
     }
 
+    /// Sets the number of frames between the checksums of confirmed frames that are compared with the remote peers. Desync detection is disabled by default, or when set to 0.
+    pub fn with_desync_detection_interval(mut self, frames: usize) -> Self {
+        self.options().desync_detection_interval = frames;
+        return self;
+    }
+
+    /// Sets the number of milliseconds a remote peer may be silent before a connection interrupted event is raised. Only raised with a disconnect timeout, and 0 disables the event.
+    pub fn with_disconnect_notify_start(mut self, milliseconds: usize) -> Self {
+        self.options().disconnect_notify_start = Duration::milliseconds(milliseconds as i64);
+        return self;
+    }
+
+    /// Sets the directory a diagnostics bundle is written to when a desync or SyncTest mismatch is detected. Recording the frames for it logs the game state every frame.
+    pub fn with_diagnostics_directory(mut self, directory: PathBuf) -> Self {
+        self.options().diagnostics_directory = Some(directory);
+        return self;
+    }
+
+    /// Sets the number of confirmed frames recorded in a diagnostics bundle.
+    pub fn with_diagnostics_frames(mut self, frames: usize) -> Self {
+        self.options().diagnostics_frames = frames;
+        return self;
+    }
+
+    /// Sets the writer a replay of the match is streamed to. Each frame's inputs are written once confirmed, following a header with the session config and the initial game state.
+    pub fn with_replay_writer<TWriter>(mut self, writer: TWriter) -> Self
+    where
+        TWriter: Write + 'static,
+    {
+        self.options().replay_writer = Some(Box::new(writer));
+        return self;
+    }
+
     /// Build the configured RMercury instance.
-    pub fn build(&mut self) -> RMercury<TGameInterface, TGameInput, TGameState> {
-        if self.was_built {
-            panic!("Builder already consumed!");
+    pub fn build(&mut self) -> RMercury<'a, TGameInterface, TGameInput, TGameState> {
+        let mut options = self.options.take().expect("Builder already consumed!");
+        if options.local_players.is_empty() {
+            let local_input_delay = options.local_input_delay;
+            options.local_players.push((None, local_input_delay));
         }
 
-        self.was_built = true;
-        let rm = RMercury::<TGameInterface, TGameInput, TGameState>::new(
-            self.m_type,
-            self.number_of_players,
-            self.number_of_spectators,
-            self.sim_executions_per_second,
-            self.local_input_delay,
-            self.game_interface,
-        );
-        return rm;
+        return RMercury::new(self.game_interface.take().unwrap(), options);
     }
 }
@@ -1,38 +1,538 @@
 use super::*;
+use rmercury_event::RMercuryEvent;
 use rmercury_input::{RMercuryInput, RMercuryInputWrapper};
+use rmercury_input_queue::RInputQueue;
+use rmercury_packet::{
+    RFrameChecksum, RInputPacket, RPacket, RPeerStatus, RSyncPacket, MAX_INPUT_PACKET_SIZE,
+};
+use rmercury_prediction::RMercuryInputPredictor;
+use rmercury_sync::{
+    assign_player_ids, check_player_claims, random_seed, RPlayerClaim, RSyncState, RSynchronizer,
+};
+use rmercury_time_sync::RTimeSync;
+use rmercury_transport::RMercuryTransport;
+use std::collections::{BTreeMap, VecDeque};
+use std::marker::PhantomData;
+use time::{Duration, Instant};
 
-pub struct RChannel<TGameInput>
+/// How long to wait for an acknowledgement before resending unacknowledged frames.
+const RESEND_INTERVAL_MILLISECONDS: i64 = 50;
+
+/// How long to go without sending before sending a packet anyway, so the peer knows the session is still alive.
+const KEEP_ALIVE_INTERVAL_MILLISECONDS: i64 = 200;
+
+/// The number of checksums kept to compare with peers that are further behind or ahead, and to resend until the peer acknowledges them.
+const MAX_CHECKSUM_HISTORY: usize = 32;
+
+/// A change in the connection to a peer that has gone silent.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RConnectionEvent {
+    /// The peer has been silent long enough to notify about it. Contains the time left before it is disconnected, or None without a disconnect timeout.
+    Interrupted(Option<Duration>),
+    /// The peer was interrupted, but started sending again.
+    Resumed,
+    /// The peer has been silent for longer than the disconnect timeout.
+    Disconnected,
+}
+
+/// The local inputs for consecutive finalized frames, along with queued inputs for frames that are not final yet.
+pub struct RInputHistory<TGameInput>
 where
     TGameInput: RMercuryInput,
     TGameInput: Copy,
     TGameInput: PartialEq,
 {
-    local_input_to_send: Vec<RMercuryInputWrapper<TGameInput>>,
+    first_frame: usize,
+    frames: VecDeque<Vec<TGameInput>>,
+    queued_inputs: Vec<RMercuryInputWrapper<TGameInput>>,
 }
 
-impl<TGameInput> RChannel<TGameInput>
+impl<TGameInput> RInputHistory<TGameInput>
 where
     TGameInput: RMercuryInput,
     TGameInput: Copy,
     TGameInput: PartialEq,
 {
     pub fn new() -> Self {
-        unimplemented!();
+        return Self {
+            first_frame: 0,
+            frames: VecDeque::new(),
+            queued_inputs: vec![],
+        };
     }
 
-    /// Get the last confirmed frame for the current channel
-    pub fn last_confirmed_frame(&self) -> usize {
-        unimplemented!();
+    /// Get the last finalized frame.
+    pub fn last_frame(&self) -> Option<usize> {
+        if self.frames.is_empty() && self.first_frame == 0 {
+            return None;
+        }
+
+        return Some(self.first_frame + self.frames.len() - 1);
+    }
+
+    /// Queue inputs for frames that are not final yet.
+    pub fn queue(&mut self, inputs: &[RMercuryInputWrapper<TGameInput>]) {
+        self.queued_inputs.extend(inputs.iter());
     }
 
-    /// Queue the local player input to be sent over the channel
-    pub fn queue_local_input(&mut self, inputs: &Vec<RMercuryInputWrapper<TGameInput>>) {
-        unimplemented!();
+    /// Remove the queued inputs of a player for a frame that is not final yet.
+    pub fn remove_queued(&mut self, frame: usize, player_id: usize) {
+        self.queued_inputs
+            .retain(|i| i.frame != frame || i.get_player_id() != player_id);
     }
 
-    /// Get the received remote player's inputs and send the queued inputs.
-    pub fn sync(&mut self) -> Vec<RMercuryInputWrapper<TGameInput>> {
-        unimplemented!();
+    /// Finalize all frames up to and including the given frame, moving their queued inputs into the history.
+    pub fn confirm(&mut self, frame: usize) {
+        let mut next_frame = self.first_frame + self.frames.len();
+
+        while next_frame <= frame {
+            let inputs = self
+                .queued_inputs
+                .iter()
+                .filter(|i| i.frame == next_frame)
+                .map(|i| i.input)
+                .collect();
+
+            self.frames.push_back(inputs);
+            next_frame += 1;
+        }
+
+        self.queued_inputs.retain(|i| i.frame > frame);
+    }
+
+    /// Drop all frames before the given frame.
+    pub fn trim(&mut self, frame: usize) {
+        while self.first_frame < frame && !self.frames.is_empty() {
+            self.frames.pop_front();
+            self.first_frame += 1;
+        }
+    }
+
+    /// Create a packet containing the finalized frames from the given frame onward, acknowledging the given frame.
+    pub fn packet_since(&self, ack_frame: usize, frame: usize) -> RInputPacket<TGameInput> {
+        let start_frame = std::cmp::max(frame, self.first_frame);
+        let frames = self
+            .frames
+            .iter()
+            .skip(start_frame - self.first_frame)
+            .cloned()
+            .collect();
+
+        return RInputPacket::new(ack_frame, 0, 0, start_frame, frames);
+    }
+}
+
+/// A connection to a single remote peer. Local inputs the peer has not acknowledged are sent over the transport, along with an acknowledgement of the frames received from the peer.
+pub struct RChannel<TGameInput, TTransport>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+    TTransport: RMercuryTransport,
+{
+    transport: TTransport,
+    session_id: u32,
+    next_remote_frame: usize,
+    acked_local_frame: usize,
+    last_sent_local_frame: Option<usize>,
+    last_sent_ack_frame: usize,
+    last_send: Option<Instant>,
+    last_receive: Option<Instant>,
+    disconnect_timeout: Option<Duration>,
+    disconnect_notify_start: Duration,
+    interrupted: bool,
+    disconnect_frame: Option<usize>,
+    peer_statuses: Vec<RPeerStatus>,
+    last_sent_peer_statuses: Vec<RPeerStatus>,
+    remote_peer_statuses: Vec<RPeerStatus>,
+    /// The local checksums the peer has not acknowledged yet, oldest first
+    local_checksums: Vec<RFrameChecksum>,
+    remote_checksums: Vec<RFrameChecksum>,
+    /// The frame after the last checksum received from the peer
+    next_remote_checksum_frame: usize,
+    last_sent_checksum_ack_frame: usize,
+    current_frame: usize,
+    remote_current_frame: Option<usize>,
+    local_frame_advantage: i32,
+    remote_frame_advantage: i32,
+    time_sync: RTimeSync,
+    synchronizer: RSynchronizer,
+    /// Why the last packet could not be serialized, if it could not
+    send_error: Option<RMercuryError>,
+    phantom_input: PhantomData<TGameInput>,
+}
+
+impl<TGameInput, TTransport> RChannel<TGameInput, TTransport>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+    TTransport: RMercuryTransport,
+{
+    /// Create a new channel that sends and receives inputs for the session over the given transport. The channel starts by synchronizing the config and player ids with the peer.
+    pub fn new(
+        transport: TTransport,
+        session_id: u32,
+        config: RMercurySessionConfig,
+        local_claims: Vec<RPlayerClaim>,
+        remote_player_id: Option<usize>,
+    ) -> Self {
+        return Self {
+            transport: transport,
+            session_id: session_id,
+            next_remote_frame: 0,
+            acked_local_frame: 0,
+            last_sent_local_frame: None,
+            last_sent_ack_frame: 0,
+            last_send: None,
+            last_receive: None,
+            disconnect_timeout: None,
+            disconnect_notify_start: Duration::zero(),
+            interrupted: false,
+            disconnect_frame: None,
+            peer_statuses: vec![],
+            last_sent_peer_statuses: vec![],
+            remote_peer_statuses: vec![],
+            local_checksums: vec![],
+            remote_checksums: vec![],
+            next_remote_checksum_frame: 0,
+            last_sent_checksum_ack_frame: 0,
+            current_frame: 0,
+            remote_current_frame: None,
+            local_frame_advantage: 0,
+            remote_frame_advantage: 0,
+            time_sync: RTimeSync::new(),
+            synchronizer: RSynchronizer::new(config, local_claims, remote_player_id),
+            send_error: None,
+            phantom_input: PhantomData,
+        };
+    }
+
+    /// Get the last frame received from the peer. All frames before it have been received as well.
+    pub fn last_received_frame(&self) -> Option<usize> {
+        if self.next_remote_frame == 0 {
+            return None;
+        }
+
+        return Some(self.next_remote_frame - 1);
+    }
+
+    /// Sets how long the peer may be silent before it is disconnected, if ever, and before the interruption is notified.
+    pub fn set_disconnect_timeout(&mut self, timeout: Option<Duration>, notify_start: Duration) {
+        self.disconnect_timeout = timeout;
+        self.disconnect_notify_start = notify_start;
+    }
+
+    /// Get why the last packet could not be serialized, such as an input payload too large for the packet format. The peer never receives such packets.
+    pub fn send_error(&self) -> Option<RMercuryError> {
+        return self.send_error;
+    }
+
+    /// Serialize the packet and send it to the peer.
+    fn send(&mut self, packet: &RPacket<TGameInput>) {
+        match packet.to_bits(self.session_id) {
+            // Transports are unreliable, so a failed send is treated the same as a dropped packet.
+            Ok(bits) => {
+                let _ = self.transport.send(&bits);
+                self.send_error = None;
+            }
+            Err(error) => self.send_error = Some(error),
+        }
+    }
+
+    /// Whether the peer was disconnected.
+    pub fn is_disconnected(&self) -> bool {
+        return self.disconnect_frame.is_some();
+    }
+
+    /// Get the first frame the local session proposes to simulate without the peer's inputs, once it was disconnected. Every frame before it was received from the peer.
+    pub fn disconnect_frame(&self) -> Option<usize> {
+        return self.disconnect_frame;
+    }
+
+    /// Stop exchanging packets with the peer.
+    pub fn disconnect(&mut self) {
+        if self.disconnect_frame.is_none() {
+            self.disconnect_frame = Some(self.next_remote_frame);
+        }
+    }
+
+    /// Sets what the local session knows about the other players, to be sent to the peer.
+    pub fn set_peer_statuses(&mut self, peer_statuses: Vec<RPeerStatus>) {
+        self.peer_statuses = peer_statuses;
+    }
+
+    /// Get what the peer last reported about the given player.
+    pub fn remote_peer_status(&self, player_id: usize) -> Option<RPeerStatus> {
+        return self
+            .remote_peer_statuses
+            .iter()
+            .find(|status| status.player_id == player_id)
+            .copied();
+    }
+
+    /// Get what the peer reported about every other player.
+    pub fn remote_peer_statuses(&self) -> &Vec<RPeerStatus> {
+        return &self.remote_peer_statuses;
+    }
+
+    /// Check how long the peer has been silent since the handshake completed, returning any change in the connection.
+    pub fn update_connection(&mut self, now: Instant) -> Option<RConnectionEvent> {
+        if self.is_disconnected() || self.sync_state() != RSyncState::Synchronized {
+            return None;
+        }
+
+        let silent_for = match self.last_receive {
+            Some(last_receive) => now - last_receive,
+            None => return None,
+        };
+
+        if let Some(disconnect_timeout) = self.disconnect_timeout {
+            if silent_for >= disconnect_timeout {
+                self.disconnect();
+                return Some(RConnectionEvent::Disconnected);
+            }
+        }
+
+        let notify_enabled = self.disconnect_notify_start > Duration::zero();
+        if self.interrupted && silent_for < self.disconnect_notify_start {
+            self.interrupted = false;
+            return Some(RConnectionEvent::Resumed);
+        }
+
+        if notify_enabled && !self.interrupted && silent_for >= self.disconnect_notify_start {
+            self.interrupted = true;
+            return Some(RConnectionEvent::Interrupted(
+                self.disconnect_timeout
+                    .map(|disconnect_timeout| disconnect_timeout - silent_for),
+            ));
+        }
+
+        return None;
+    }
+
+    /// Whether any packet has been received from the peer.
+    pub fn is_connected(&self) -> bool {
+        return self.last_receive.is_some();
+    }
+
+    /// Get the number of completed round trips of the handshake, along with the number needed to synchronize.
+    pub fn sync_progress(&self) -> (usize, usize) {
+        return self.synchronizer.progress();
+    }
+
+    /// Get the state of the handshake with the peer.
+    pub fn sync_state(&self) -> RSyncState {
+        return self.synchronizer.state();
+    }
+
+    /// Get the round trip time to the peer measured during the handshake.
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        return self.synchronizer.round_trip_time();
+    }
+
+    /// Get the player ids the peer's local players claimed during the handshake.
+    pub fn remote_claims(&self) -> Option<&Vec<RPlayerClaim>> {
+        return self.synchronizer.remote_claims();
+    }
+
+    /// Update the frame the local session is simulating, recording how far it is ahead of the peer.
+    pub fn advance_frame(&mut self, current_frame: usize) {
+        self.current_frame = current_frame;
+
+        if let Some(remote_current_frame) = self.remote_current_frame {
+            self.local_frame_advantage = current_frame as i32 - remote_current_frame as i32;
+            self.time_sync
+                .advance_frame(self.local_frame_advantage, self.remote_frame_advantage);
+        }
+    }
+
+    /// Get the number of frames the local session should wait for the peer to catch up.
+    pub fn recommended_frame_delay(&self) -> usize {
+        return self.time_sync.recommended_frame_delay();
+    }
+
+    /// Add the checksum of a checksummed confirmed frame, sent along with every input packet until the peer acknowledges it.
+    pub fn add_local_checksum(&mut self, checksum: RFrameChecksum) {
+        self.local_checksums.push(checksum);
+        if self.local_checksums.len() > MAX_CHECKSUM_HISTORY {
+            self.local_checksums.remove(0);
+        }
+    }
+
+    /// Take the checksums received from the peer since the last call, oldest first.
+    pub fn take_remote_checksums(&mut self) -> Vec<RFrameChecksum> {
+        return std::mem::take(&mut self.remote_checksums);
+    }
+
+    /// Merge a status the peer reported about another player. Frames only move forward, a disconnect is only replaced by the player leaving, and leaving is final.
+    fn receive_peer_status(&mut self, peer_status: RPeerStatus) {
+        let existing = self
+            .remote_peer_statuses
+            .iter_mut()
+            .find(|status| status.player_id == peer_status.player_id);
+
+        match existing {
+            Some(status) if status.left => {}
+            Some(status) if peer_status.left => *status = peer_status,
+            Some(status) if status.disconnected => {}
+            Some(status) if peer_status.disconnected => *status = peer_status,
+            Some(status) => status.next_frame = status.next_frame.max(peer_status.next_frame),
+            None => self.remote_peer_statuses.push(peer_status),
+        }
+    }
+
+    /// Get the received remote player's inputs and send the unacknowledged local input history.
+    pub fn sync(
+        &mut self,
+        local_history: &RInputHistory<TGameInput>,
+        now: Instant,
+    ) -> Vec<RMercuryInputWrapper<TGameInput>> {
+        // Receive
+        let mut remote_inputs = vec![];
+        {
+            for datagram in self.transport.poll().iter() {
+                // Malformed packets or packets from other sessions are dropped.
+                let packet = match RPacket::<TGameInput>::from_bits(datagram, self.session_id) {
+                    Ok(packet) => packet,
+                    Err(_) => continue,
+                };
+
+                self.last_receive = Some(now);
+
+                let packet = match packet {
+                    RPacket::Input(packet) => packet,
+                    RPacket::SyncRequest(request) => {
+                        self.synchronizer
+                            .receive_request(request.config, request.claims);
+
+                        let reply = RSyncPacket::new(
+                            request.nonce,
+                            self.synchronizer.config(),
+                            self.synchronizer.local_claims().clone(),
+                        );
+                        self.send(&RPacket::SyncReply(reply));
+                        continue;
+                    }
+                    RPacket::SyncReply(reply) => {
+                        self.synchronizer.receive_reply(
+                            reply.nonce,
+                            reply.config,
+                            reply.claims,
+                            now,
+                        );
+                        continue;
+                    }
+                };
+
+                if packet.ack_frame > self.acked_local_frame {
+                    self.acked_local_frame = packet.ack_frame;
+                }
+
+                // Packets can arrive out of order, so only the latest frame advantage is kept.
+                let is_latest = match self.remote_current_frame {
+                    Some(frame) => packet.current_frame >= frame,
+                    None => true,
+                };
+                if is_latest {
+                    self.remote_current_frame = Some(packet.current_frame);
+                    self.remote_frame_advantage = packet.frame_advantage;
+                }
+
+                for peer_status in packet.peer_statuses.iter() {
+                    self.receive_peer_status(*peer_status);
+                }
+
+                self.local_checksums
+                    .retain(|checksum| checksum.frame >= packet.checksum_ack_frame);
+
+                // Checksums are repeated in every packet until acknowledged, so only new ones are kept.
+                for checksum in packet.checksums.iter() {
+                    if checksum.frame >= self.next_remote_checksum_frame {
+                        self.next_remote_checksum_frame = checksum.frame + 1;
+                        self.remote_checksums.push(*checksum);
+                    }
+                }
+
+                // Inputs that would leave a gap in the received frames are dropped.
+                if packet.start_frame > self.next_remote_frame {
+                    continue;
+                }
+
+                for (i, inputs) in packet.frames.iter().enumerate() {
+                    let frame = packet.start_frame + i;
+                    if frame < self.next_remote_frame {
+                        continue;
+                    }
+
+                    for input in inputs.iter() {
+                        remote_inputs.push(RMercuryInputWrapper::new(*input, frame));
+                    }
+
+                    self.next_remote_frame = frame + 1;
+                }
+            }
+        }
+
+        // Send
+        if let Some(nonce) = self.synchronizer.poll_request(now) {
+            let request = RSyncPacket::new(
+                nonce,
+                self.synchronizer.config(),
+                self.synchronizer.local_claims().clone(),
+            );
+            self.send(&RPacket::SyncRequest(request));
+        }
+
+        {
+            let last_local_frame = local_history.last_frame();
+            let has_new_frame = last_local_frame != self.last_sent_local_frame;
+            let has_new_ack = self.next_remote_frame != self.last_sent_ack_frame
+                || self.next_remote_checksum_frame != self.last_sent_checksum_ack_frame;
+            let has_new_peer_statuses = self.peer_statuses != self.last_sent_peer_statuses;
+
+            let has_unacked_frames = match last_local_frame {
+                Some(frame) => frame >= self.acked_local_frame,
+                None => false,
+            };
+            let resend_due = (has_unacked_frames || !self.local_checksums.is_empty())
+                && match self.last_send {
+                    Some(last_send) => {
+                        now - last_send >= Duration::milliseconds(RESEND_INTERVAL_MILLISECONDS)
+                    }
+                    None => true,
+                };
+            let keep_alive_due = match self.last_send {
+                Some(last_send) => {
+                    now - last_send >= Duration::milliseconds(KEEP_ALIVE_INTERVAL_MILLISECONDS)
+                }
+                None => false,
+            };
+
+            if has_new_frame || has_new_ack || has_new_peer_statuses || resend_due || keep_alive_due
+            {
+                let mut packet =
+                    local_history.packet_since(self.next_remote_frame, self.acked_local_frame);
+                packet.current_frame = self.current_frame;
+                packet.frame_advantage = self.local_frame_advantage;
+                packet.peer_statuses = self.peer_statuses.clone();
+                packet.checksum_ack_frame = self.next_remote_checksum_frame;
+                packet.checksums = self.local_checksums.clone();
+
+                // Peers that are further behind catch up over several packets.
+                packet.truncate_to_size(MAX_INPUT_PACKET_SIZE);
+
+                self.send(&RPacket::Input(packet));
+
+                self.last_sent_local_frame = last_local_frame;
+                self.last_sent_ack_frame = self.next_remote_frame;
+                self.last_sent_checksum_ack_frame = self.next_remote_checksum_frame;
+                self.last_sent_peer_statuses = self.peer_statuses.clone();
+                self.last_send = Some(now);
+            }
+        }
+
+        return remote_inputs;
     }
 }
 
@@ -42,8 +542,25 @@ where
     TGameInput: Copy,
     TGameInput: PartialEq,
 {
-    last_confirmed_local_input_frame: usize,
-    channels: Vec<RChannel<TGameInput>>,
+    local_history: RInputHistory<TGameInput>,
+    channels: Vec<RChannel<TGameInput, Box<dyn RMercuryTransport>>>,
+    input_queues: Vec<RInputQueue<TGameInput>>,
+    input_predictor: Box<dyn RMercuryInputPredictor<TGameInput>>,
+    local_claims: Vec<RPlayerClaim>,
+    number_of_players: usize,
+    events: Vec<RMercuryEvent>,
+    /// The ids of each peer's players, known once the handshake has completed
+    peer_player_ids: Option<Vec<Vec<usize>>>,
+    /// The agreed first frame each disconnected player is simulated without their inputs, by player id
+    disconnect_frames: BTreeMap<usize, usize>,
+    /// What the local session reports about each player it disconnected or that left, by player id, until agreed with the peers
+    disconnect_statuses: BTreeMap<usize, RPeerStatus>,
+    /// The number of frames between the checksums compared with the peers, or 0 if disabled
+    desync_detection_interval: usize,
+    /// The checksums of the most recent checksummed confirmed frames
+    local_checksums: Vec<RFrameChecksum>,
+    /// The checksums received from each peer for frames the local session has not confirmed yet
+    remote_checksums: Vec<Vec<RFrameChecksum>>,
 }
 
 impl<TGameInput> RChannelManager<TGameInput>
@@ -52,53 +569,1255 @@ where
     TGameInput: Copy,
     TGameInput: PartialEq,
 {
-    pub fn new() -> Self {
+    /// Create a new manager with a channel to every peer. The local players and peers are paired with their player id, if it is known up front.
+    pub fn new(
+        session_id: u32,
+        transports: Vec<(Option<usize>, Box<dyn RMercuryTransport>)>,
+        input_predictor: Box<dyn RMercuryInputPredictor<TGameInput>>,
+        config: RMercurySessionConfig,
+        local_player_ids: Vec<Option<usize>>,
+    ) -> Self {
+        // Sorted seeds keep the negotiated ids of the local players in the order they were added.
+        let mut seeds: Vec<u32> = local_player_ids.iter().map(|_| random_seed()).collect();
+        seeds.sort();
+
+        let local_claims: Vec<RPlayerClaim> = local_player_ids
+            .iter()
+            .zip(seeds.iter())
+            .map(|(player_id, seed)| RPlayerClaim::new(*player_id, *seed))
+            .collect();
+
+        let channels: Vec<RChannel<TGameInput, Box<dyn RMercuryTransport>>> = transports
+            .into_iter()
+            .map(|(remote_player_id, transport)| {
+                RChannel::new(
+                    transport,
+                    session_id,
+                    config,
+                    local_claims.clone(),
+                    remote_player_id,
+                )
+            })
+            .collect();
+
+        let input_queues = channels.iter().map(|_| RInputQueue::new()).collect();
+        let remote_checksums = channels.iter().map(|_| vec![]).collect();
+
         return Self {
-            channels: vec![],
-            last_confirmed_local_input_frame: 0,
+            channels: channels,
+            local_history: RInputHistory::new(),
+            input_queues: input_queues,
+            input_predictor: input_predictor,
+            local_claims: local_claims,
+            number_of_players: config.number_of_players,
+            events: vec![],
+            peer_player_ids: None,
+            disconnect_frames: BTreeMap::new(),
+            disconnect_statuses: BTreeMap::new(),
+            desync_detection_interval: config.desync_detection_interval,
+            local_checksums: vec![],
+            remote_checksums: remote_checksums,
         };
     }
 
-    /// Retrieve the last frame that is confirmed for all players
-    pub fn last_confirmed_frame(&self) -> usize {
-        let mut last_confirmed_frame = self.last_confirmed_local_input_frame;
+    /// Get the ids of the local players, in the order they were added. Known once the handshake has completed with every peer, as ids without an explicit assignment are negotiated with the peers.
+    pub fn local_player_ids(&self) -> Option<Vec<usize>> {
+        return self
+            .player_ids()
+            .map(|(local_player_ids, _)| local_player_ids);
+    }
+
+    /// Get the ids of the local players, along with the ids of each peer's players.
+    fn player_ids(&self) -> Option<(Vec<usize>, Vec<Vec<usize>>)> {
+        if !self.is_synchronized() {
+            return None;
+        }
+
+        let mut claims = self.local_claims.clone();
+        let mut claim_counts = vec![];
         for channel in self.channels.iter() {
-            let channel_last_confirmed_frame = channel.last_confirmed_frame();
-            if channel_last_confirmed_frame <= last_confirmed_frame {
-                last_confirmed_frame = channel_last_confirmed_frame;
-            }
+            let remote_claims = channel.remote_claims()?;
+            claims.extend(remote_claims.iter());
+            claim_counts.push(remote_claims.len());
         }
 
-        return last_confirmed_frame;
+        let player_ids = assign_player_ids(&claims);
+
+        let mut offset = self.local_claims.len();
+        let mut peer_player_ids = vec![];
+        for claim_count in claim_counts {
+            peer_player_ids.push(player_ids[offset..offset + claim_count].to_vec());
+            offset += claim_count;
+        }
+
+        return Some((
+            player_ids[..self.local_claims.len()].to_vec(),
+            peer_player_ids,
+        ));
     }
 
-    /// Queue the local inputs to send over the network
-    pub fn queue_local_input(&mut self, inputs: &Vec<RMercuryInputWrapper<TGameInput>>) {
-        for input in inputs.iter() {
-            if input.frame >= self.last_confirmed_local_input_frame {
-                self.last_confirmed_local_input_frame = input.frame;
+    /// Stop exchanging packets with a peer, such as when kicking a player. Every other peer disconnects it as well.
+    pub fn disconnect_peer(&mut self, peer: usize) {
+        if let Some(channel) = self.channels.get_mut(peer) {
+            if !channel.is_disconnected() {
+                channel.disconnect();
+                self.events
+                    .push(RMercuryEvent::DisconnectedFromPeer { peer: peer });
             }
         }
+    }
+
+    /// Remove a local player from the session from the given frame on. Every peer is told the frame, and simulates the player without their inputs from it.
+    pub fn leave(&mut self, player_id: usize, frame: usize) {
+        if self.disconnect_statuses.contains_key(&player_id) {
+            return;
+        }
+
+        self.disconnect_statuses.insert(
+            player_id,
+            RPeerStatus {
+                player_id: player_id,
+                next_frame: frame,
+                disconnected: true,
+                left: true,
+            },
+        );
+    }
+
+    /// Whether a local player left the session.
+    pub fn has_left(&self, player_id: usize) -> bool {
+        return match self.disconnect_statuses.get(&player_id) {
+            Some(status) => status.left,
+            None => false,
+        };
+    }
+
+    /// Get the ids of the players that are simulated without their inputs on the given frame, as they left or their peer disconnected.
+    pub fn disconnected_player_ids(&self, frame: usize) -> Vec<usize> {
+        return self
+            .disconnect_frames
+            .iter()
+            .filter(|(_, disconnect_frame)| **disconnect_frame <= frame)
+            .map(|(player_id, _)| *player_id)
+            .collect();
+    }
 
+    /// Whether a player is simulated without their inputs on the given frame.
+    fn is_player_disconnected(&self, player_id: usize, frame: usize) -> bool {
+        return match self.disconnect_frames.get(&player_id) {
+            Some(disconnect_frame) => *disconnect_frame <= frame,
+            None => false,
+        };
+    }
+
+    /// Sets how long a peer may be silent before it is disconnected, if ever, and before the interruption is notified.
+    pub fn set_disconnect_timeout(&mut self, timeout: Option<Duration>, notify_start: Duration) {
         for channel in self.channels.iter_mut() {
-            channel.queue_local_input(&inputs);
+            channel.set_disconnect_timeout(timeout, notify_start);
         }
     }
 
-    /// Sync up all inputs, getting remote player's inputs + sending current inputs.
-    pub fn execute(&mut self) -> Vec<RMercuryInputWrapper<TGameInput>> {
-        let mut inputs = vec![];
+    /// Add the checksum of a confirmed frame, sending it to every peer to compare with their own.
+    pub fn add_confirmed_checksum(&mut self, checksum: RFrameChecksum) {
+        self.local_checksums.push(checksum);
+        if self.local_checksums.len() > MAX_CHECKSUM_HISTORY {
+            self.local_checksums.remove(0);
+        }
 
         for channel in self.channels.iter_mut() {
-            let mut remote_inputs = channel.sync();
+            channel.add_local_checksum(checksum);
+        }
+    }
+
+    /// Take the events raised by the peers since the last call.
+    pub fn take_events(&mut self) -> Vec<RMercuryEvent> {
+        return std::mem::take(&mut self.events);
+    }
+
+    /// Whether the handshake has completed with every peer, and the players of all of them fit in the session together.
+    pub fn is_synchronized(&self) -> bool {
+        return self
+            .channels
+            .iter()
+            .all(|channel| channel.sync_state() == RSyncState::Synchronized)
+            && self.claims_error().is_none();
+    }
 
-            if remote_inputs.is_empty() {
-                // Add prediction?
+    /// Get the error of the first peer the handshake failed with, or of the players of the peers not fitting in the session together.
+    pub fn sync_error(&self) -> Option<RMercuryError> {
+        for channel in self.channels.iter() {
+            if let RSyncState::Failed(error) = channel.sync_state() {
+                return Some(error);
             }
+        }
+
+        return self.claims_error();
+    }
 
-            inputs.append(&mut remote_inputs);
+    /// Get why a packet to any peer could not be serialized, if one could not.
+    pub fn send_error(&self) -> Option<RMercuryError> {
+        return self
+            .channels
+            .iter()
+            .filter_map(|channel| channel.send_error())
+            .next();
+    }
+
+    /// Check the player ids claimed by the local players and every peer together, once all of them are known. Each handshake only checks the claims of a single peer.
+    fn claims_error(&self) -> Option<RMercuryError> {
+        let mut claims = self.local_claims.clone();
+        for channel in self.channels.iter() {
+            claims.extend(channel.remote_claims()?.iter());
         }
 
+        return check_player_claims(&claims, self.number_of_players).err();
+    }
+
+    /// Get the largest round trip time to any peer, measured during the handshake.
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        return self
+            .channels
+            .iter()
+            .filter_map(|channel| channel.round_trip_time())
+            .max();
+    }
+
+    /// Whether the inputs of every player have been received for the given frame.
+    pub fn is_frame_confirmed(&self, frame: usize) -> bool {
+        let local_frame_confirmed = match self.local_history.last_frame() {
+            Some(last_frame) => frame <= last_frame,
+            None => false,
+        };
+
+        return local_frame_confirmed
+            && (0..self.channels.len()).all(|peer| self.is_remote_frame_confirmed(peer, frame));
+    }
+
+    /// Whether a peer's inputs for the given frame have been received by the local session and every other connected peer. Every peer simulates a disconnected player the same way from the agreed frame, so none of its inputs are needed from then on.
+    fn is_remote_frame_confirmed(&self, peer: usize, frame: usize) -> bool {
+        let received = match self.channels[peer].last_received_frame() {
+            Some(last_frame) => frame <= last_frame,
+            None => false,
+        };
+
+        let player_ids: Vec<usize> = match &self.peer_player_ids {
+            Some(peer_player_ids) => peer_player_ids[peer]
+                .iter()
+                .copied()
+                .filter(|player_id| !self.is_player_disconnected(*player_id, frame))
+                .collect(),
+            None => return received,
+        };
+
+        if player_ids.is_empty() {
+            return true;
+        }
+
+        if !received {
+            return false;
+        }
+
+        // The agreed disconnect frame may be as early as the first frame another peer is missing.
+        return self
+            .channels
+            .iter()
+            .enumerate()
+            .filter(|(other_peer, channel)| *other_peer != peer && !channel.is_disconnected())
+            .all(|(_, channel)| {
+                player_ids
+                    .iter()
+                    .all(|player_id| match channel.remote_peer_status(*player_id) {
+                        Some(status) => frame < status.next_frame,
+                        None => false,
+                    })
+            });
+    }
+
+    /// Queue the local inputs to send over the network
+    pub fn queue_local_input(&mut self, inputs: &[RMercuryInputWrapper<TGameInput>]) {
+        self.local_history.queue(inputs);
+    }
+
+    /// Remove the local inputs of a player for a frame that has not been marked as final yet.
+    pub fn remove_local_input(&mut self, frame: usize, player_id: usize) {
+        self.local_history.remove_queued(frame, player_id);
+    }
+
+    /// Get the first local frame that has not been marked as final. Inputs for earlier frames have already been sent.
+    pub fn next_local_frame(&self) -> usize {
+        return match self.local_history.last_frame() {
+            Some(last_frame) => last_frame + 1,
+            None => 0,
+        };
+    }
+
+    /// Mark all local inputs up to and including the given frame as final.
+    pub fn confirm_local_frame(&mut self, frame: usize) {
+        self.local_history.confirm(frame);
+    }
+
+    /// Get the remote players' inputs for the given frame. Inputs that have not been received yet are predicted. Disconnected players have no inputs.
+    pub fn remote_inputs(&mut self, frame: usize) -> Vec<TGameInput> {
+        let mut inputs = vec![];
+
+        for input_queue in self.input_queues.iter_mut() {
+            inputs.append(&mut input_queue.inputs(frame, self.input_predictor.as_ref()));
+        }
+
+        inputs.retain(|input| !self.is_player_disconnected(input.get_player_id(), frame));
+
         return inputs;
     }
+
+    /// Update the frame the local session is simulating. Called once per simulated frame to keep the peers' frame advantages up to date.
+    pub fn advance_frame(&mut self, current_frame: usize) {
+        for channel in self.channels.iter_mut() {
+            channel.advance_frame(current_frame);
+        }
+    }
+
+    /// Get the number of frames the local session should wait for the slowest peer to catch up.
+    pub fn recommended_frame_delay(&self) -> usize {
+        return self
+            .channels
+            .iter()
+            .filter(|channel| !channel.is_disconnected())
+            .map(|channel| channel.recommended_frame_delay())
+            .max()
+            .unwrap_or(0);
+    }
+
+    /// Drop all remote inputs before the given frame. They will not be simulated again.
+    pub fn trim_remote_inputs(&mut self, frame: usize) {
+        for input_queue in self.input_queues.iter_mut() {
+            input_queue.trim(frame);
+        }
+    }
+
+    /// Sync up all inputs, getting remote player's inputs + sending current inputs. Returns the earliest frame whose predicted inputs did not match the received ones.
+    pub fn execute(&mut self, now: Instant) -> Option<usize> {
+        let mut first_incorrect_frame: Option<usize> = None;
+
+        if self.peer_player_ids.is_none() {
+            self.peer_player_ids = self
+                .player_ids()
+                .map(|(_, peer_player_ids)| peer_player_ids);
+        }
+        self.update_peer_statuses();
+
+        for (peer, (channel, input_queue)) in self
+            .channels
+            .iter_mut()
+            .zip(self.input_queues.iter_mut())
+            .enumerate()
+        {
+            // Disconnected peers are no longer heard from.
+            if channel.is_disconnected() {
+                continue;
+            }
+
+            let first_new_frame = channel.next_remote_frame;
+            let was_connected = channel.is_connected();
+            let previous_progress = channel.sync_progress();
+
+            let remote_inputs = channel.sync(&self.local_history, now);
+
+            if !was_connected && channel.is_connected() {
+                self.events
+                    .push(RMercuryEvent::ConnectedToPeer { peer: peer });
+            }
+
+            let (count, total) = channel.sync_progress();
+            if count > previous_progress.0 {
+                self.events.push(RMercuryEvent::SynchronizingWithPeer {
+                    peer: peer,
+                    count: count,
+                    total: total,
+                });
+
+                if channel.sync_state() == RSyncState::Synchronized {
+                    self.events
+                        .push(RMercuryEvent::SynchronizedWithPeer { peer: peer });
+                }
+            }
+
+            for frame in first_new_frame..channel.next_remote_frame {
+                let inputs = remote_inputs
+                    .iter()
+                    .filter(|x| x.frame == frame)
+                    .map(|x| x.input)
+                    .collect();
+
+                input_queue.add_confirmed(inputs);
+            }
+
+            match channel.update_connection(now) {
+                Some(RConnectionEvent::Interrupted(disconnect_timeout)) => {
+                    self.events.push(RMercuryEvent::ConnectionInterrupted {
+                        peer: peer,
+                        disconnect_timeout: disconnect_timeout,
+                    });
+                }
+                Some(RConnectionEvent::Resumed) => {
+                    self.events
+                        .push(RMercuryEvent::ConnectionResumed { peer: peer });
+                }
+                Some(RConnectionEvent::Disconnected) => {
+                    self.events
+                        .push(RMercuryEvent::DisconnectedFromPeer { peer: peer });
+                }
+                None => {}
+            }
+
+            if let Some(incorrect_frame) = input_queue.take_first_incorrect_frame() {
+                first_incorrect_frame = match first_incorrect_frame {
+                    Some(frame) if frame < incorrect_frame => Some(frame),
+                    _ => Some(incorrect_frame),
+                };
+            }
+        }
+
+        self.propagate_disconnects();
+        self.propose_disconnects();
+        self.detect_desyncs();
+        if let Some(incorrect_frame) = self.agree_on_disconnect_frames() {
+            first_incorrect_frame = match first_incorrect_frame {
+                Some(frame) if frame < incorrect_frame => Some(frame),
+                _ => Some(incorrect_frame),
+            };
+        }
+
+        // Frames every peer has acknowledged will never be resent.
+        let mut acked_frame = usize::MAX;
+        for channel in self
+            .channels
+            .iter()
+            .filter(|channel| !channel.is_disconnected())
+        {
+            let channel_acked_frame = channel.acked_local_frame;
+            if channel_acked_frame < acked_frame {
+                acked_frame = channel_acked_frame;
+            }
+        }
+        self.local_history.trim(acked_frame);
+
+        return first_incorrect_frame;
+    }
+
+    /// Tell every peer how far the local session has received the inputs of the other peers' players, which of them were disconnected, and which local players left.
+    fn update_peer_statuses(&mut self) {
+        let peer_player_ids = match &self.peer_player_ids {
+            Some(peer_player_ids) => peer_player_ids,
+            None => return,
+        };
+
+        let mut peer_statuses = vec![];
+        for (channel, player_ids) in self.channels.iter().zip(peer_player_ids.iter()) {
+            for player_id in player_ids.iter() {
+                peer_statuses.push(match self.disconnect_statuses.get(player_id) {
+                    Some(status) => *status,
+                    None => RPeerStatus {
+                        player_id: *player_id,
+                        next_frame: channel.next_remote_frame,
+                        disconnected: false,
+                        left: false,
+                    },
+                });
+            }
+        }
+
+        for status in self.disconnect_statuses.values() {
+            let is_local = !peer_player_ids
+                .iter()
+                .any(|player_ids| player_ids.contains(&status.player_id));
+            if is_local {
+                peer_statuses.push(*status);
+            }
+        }
+
+        for (channel, player_ids) in self.channels.iter_mut().zip(peer_player_ids.iter()) {
+            // A peer knows how far its own inputs were received through the acknowledgements.
+            let statuses = peer_statuses
+                .iter()
+                .filter(|status| !player_ids.contains(&status.player_id))
+                .copied()
+                .collect();
+
+            channel.set_peer_statuses(statuses);
+        }
+    }
+
+    /// Compare the checksums received from the peers with the local ones, raising an event for each frame that differs. Checksums for frames the local session has not confirmed yet are compared once it has, as long as they fit in the checksum history.
+    fn detect_desyncs(&mut self) {
+        let last_local_frame = self.local_checksums.last().map(|checksum| checksum.frame);
+
+        for (peer, channel) in self.channels.iter_mut().enumerate() {
+            let mut received_checksums = channel.take_remote_checksums();
+            if self.desync_detection_interval == 0 {
+                continue;
+            }
+
+            let remote_checksums = &mut self.remote_checksums[peer];
+            remote_checksums.append(&mut received_checksums);
+
+            for remote_checksum in remote_checksums.iter() {
+                let local_checksum = self
+                    .local_checksums
+                    .iter()
+                    .find(|checksum| checksum.frame == remote_checksum.frame);
+
+                match local_checksum {
+                    Some(local_checksum) if local_checksum.checksum != remote_checksum.checksum => {
+                        self.events.push(RMercuryEvent::DesyncDetected {
+                            peer: peer,
+                            frame: remote_checksum.frame,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+
+            remote_checksums.retain(|checksum| match last_local_frame {
+                Some(last_frame) => checksum.frame > last_frame,
+                None => true,
+            });
+            if remote_checksums.len() > MAX_CHECKSUM_HISTORY {
+                let excess = remote_checksums.len() - MAX_CHECKSUM_HISTORY;
+                remote_checksums.drain(..excess);
+            }
+        }
+    }
+
+    /// Disconnect every peer that another connected peer reported as disconnected. Players that left are not, as their peer may still have other players.
+    fn propagate_disconnects(&mut self) {
+        let peer_player_ids = match &self.peer_player_ids {
+            Some(peer_player_ids) => peer_player_ids,
+            None => return,
+        };
+
+        let mut disconnected_peers = vec![];
+        for (peer, player_ids) in peer_player_ids.iter().enumerate() {
+            if self.channels[peer].is_disconnected() {
+                continue;
+            }
+
+            let reported = self
+                .channels
+                .iter()
+                .filter(|channel| !channel.is_disconnected())
+                .any(|channel| {
+                    channel.remote_peer_statuses().iter().any(|status| {
+                        status.disconnected
+                            && !status.left
+                            && player_ids.contains(&status.player_id)
+                    })
+                });
+
+            if reported {
+                disconnected_peers.push(peer);
+            }
+        }
+
+        for peer in disconnected_peers {
+            self.disconnect_peer(peer);
+        }
+    }
+
+    /// Propose disconnecting the players of every disconnected peer from the first frame the local session is missing, and the players a connected peer reported as having left from the frame they left on.
+    fn propose_disconnects(&mut self) {
+        let peer_player_ids = match &self.peer_player_ids {
+            Some(peer_player_ids) => peer_player_ids,
+            None => return,
+        };
+
+        for (channel, player_ids) in self.channels.iter().zip(peer_player_ids.iter()) {
+            for player_id in player_ids.iter() {
+                if self.disconnect_frames.contains_key(player_id) {
+                    continue;
+                }
+
+                // Every peer is told the same frame a player left on, so it replaces a proposed disconnect.
+                let left_status = self
+                    .channels
+                    .iter()
+                    .filter(|channel| !channel.is_disconnected())
+                    .filter_map(|channel| channel.remote_peer_status(*player_id))
+                    .find(|status| status.left);
+                if let Some(status) = left_status {
+                    self.disconnect_statuses.insert(*player_id, status);
+                    continue;
+                }
+
+                if self.disconnect_statuses.contains_key(player_id) {
+                    continue;
+                }
+
+                if let Some(disconnect_frame) = channel.disconnect_frame() {
+                    self.disconnect_statuses.insert(
+                        *player_id,
+                        RPeerStatus {
+                            player_id: *player_id,
+                            next_frame: disconnect_frame,
+                            disconnected: true,
+                            left: false,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Agree on the disconnect frame of each proposed player. Players that left do so on the frame they announced, while disconnected players wait for every connected peer to disconnect them as well, using the earliest proposed frame. Returns the earliest frame that has to be simulated again.
+    fn agree_on_disconnect_frames(&mut self) -> Option<usize> {
+        let mut first_incorrect_frame: Option<usize> = None;
+
+        let proposed: Vec<RPeerStatus> = self
+            .disconnect_statuses
+            .values()
+            .filter(|status| !self.disconnect_frames.contains_key(&status.player_id))
+            .copied()
+            .collect();
+
+        for status in proposed {
+            let mut disconnect_frame = status.next_frame;
+
+            if !status.left {
+                let mut agreed = true;
+                for channel in self
+                    .channels
+                    .iter()
+                    .filter(|channel| !channel.is_disconnected())
+                {
+                    match channel.remote_peer_status(status.player_id) {
+                        Some(remote_status) if remote_status.disconnected => {
+                            disconnect_frame = disconnect_frame.min(remote_status.next_frame);
+                        }
+                        _ => agreed = false,
+                    }
+                }
+
+                if !agreed {
+                    continue;
+                }
+            }
+
+            self.disconnect_frames
+                .insert(status.player_id, disconnect_frame);
+            self.events.push(RMercuryEvent::PlayerDisconnected {
+                player_id: status.player_id,
+                frame: disconnect_frame,
+            });
+
+            first_incorrect_frame = match first_incorrect_frame {
+                Some(frame) if frame < disconnect_frame => Some(frame),
+                _ => Some(disconnect_frame),
+            };
+
+            // A peer without any players left has nothing more to send.
+            let peer_player_ids = match &self.peer_player_ids {
+                Some(peer_player_ids) => peer_player_ids,
+                None => continue,
+            };
+            let peer = match peer_player_ids
+                .iter()
+                .position(|player_ids| player_ids.contains(&status.player_id))
+            {
+                Some(peer) => peer,
+                None => continue,
+            };
+            let disconnect_frames: Option<Vec<usize>> = peer_player_ids[peer]
+                .iter()
+                .map(|player_id| self.disconnect_frames.get(player_id).copied())
+                .collect();
+
+            if let Some(last_frame) = disconnect_frames.and_then(|frames| frames.into_iter().max())
+            {
+                self.disconnect_peer(peer);
+                self.input_queues[peer].disconnect(last_frame);
+
+                if let Some(incorrect_frame) = self.input_queues[peer].take_first_incorrect_frame()
+                {
+                    first_incorrect_frame = match first_incorrect_frame {
+                        Some(frame) if frame < incorrect_frame => Some(frame),
+                        _ => Some(incorrect_frame),
+                    };
+                }
+            }
+        }
+
+        return first_incorrect_frame;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmercury_test_game::{manager_pair, test_claims, test_config, TestInput};
+    use rmercury_transport::RMercuryMemoryTransport;
+
+    const SESSION_ID: u32 = 7;
+
+    /// Create two connected channels that have completed the handshake.
+    fn channel_pair() -> (
+        RChannel<TestInput, RMercuryMemoryTransport>,
+        RChannel<TestInput, RMercuryMemoryTransport>,
+    ) {
+        let (transport_a, transport_b) = RMercuryMemoryTransport::pair();
+        let mut channel_a =
+            RChannel::new(transport_a, SESSION_ID, test_config(), test_claims(), None);
+        let mut channel_b =
+            RChannel::new(transport_b, SESSION_ID, test_config(), test_claims(), None);
+
+        let history = RInputHistory::new();
+        while channel_a.sync_state() == RSyncState::Synchronizing
+            || channel_b.sync_state() == RSyncState::Synchronizing
+        {
+            channel_a.sync(&history, Instant::now());
+            channel_b.sync(&history, Instant::now());
+        }
+
+        channel_a.transport.poll();
+        channel_b.transport.poll();
+
+        return (channel_a, channel_b);
+    }
+
+    #[test]
+    fn rinput_history_confirm_fills_skipped_frames() {
+        let mut history = RInputHistory::<TestInput>::new();
+        let input = TestInput::new(1, 7);
+        history.queue(&[RMercuryInputWrapper::new(input, 2)]);
+        history.queue(&[RMercuryInputWrapper::new(input, 4)]);
+
+        history.confirm(2);
+
+        assert_eq!(Some(2), history.last_frame());
+        let expected = RInputPacket::new(0, 0, 0, 0, vec![vec![], vec![], vec![input]]);
+        assert_eq!(expected, history.packet_since(0, 0));
+    }
+
+    #[test]
+    fn rinput_history_remove_queued_only_removes_player_frame() {
+        let mut history = RInputHistory::<TestInput>::new();
+        history.queue(&[RMercuryInputWrapper::new(TestInput::new(1, 1), 0)]);
+        history.queue(&[RMercuryInputWrapper::new(TestInput::new(2, 2), 0)]);
+        history.queue(&[RMercuryInputWrapper::new(TestInput::new(1, 3), 1)]);
+
+        history.remove_queued(0, 1);
+        history.confirm(1);
+
+        let expected = RInputPacket::new(
+            0,
+            0,
+            0,
+            0,
+            vec![vec![TestInput::new(2, 2)], vec![TestInput::new(1, 3)]],
+        );
+        assert_eq!(expected, history.packet_since(0, 0));
+    }
+
+    #[test]
+    fn rinput_history_trim_drops_old_frames() {
+        let mut history = RInputHistory::<TestInput>::new();
+        history.confirm(5);
+
+        history.trim(4);
+
+        assert_eq!(Some(5), history.last_frame());
+        assert_eq!(4, history.packet_since(0, 0).start_frame);
+        assert_eq!(2, history.packet_since(0, 0).frames.len());
+    }
+
+    #[test]
+    fn rchannel_sync_sends_confirmed_input_to_remote() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        let input = RMercuryInputWrapper::new(TestInput::new(1, 7), 3);
+        history.queue(&[input]);
+        history.confirm(3);
+        channel_a.sync(&history, Instant::now());
+
+        let actual = channel_b.sync(&RInputHistory::new(), Instant::now());
+        assert_eq!(vec![input], actual);
+        assert_eq!(Some(3), channel_b.last_received_frame());
+    }
+
+    #[test]
+    fn rchannel_sync_unconfirmed_input_is_not_sent() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        history.queue(&[RMercuryInputWrapper::new(TestInput::new(1, 7), 3)]);
+        history.confirm(2);
+        channel_a.sync(&history, Instant::now());
+
+        let actual = channel_b.sync(&RInputHistory::new(), Instant::now());
+        assert_eq!(true, actual.is_empty());
+        assert_eq!(Some(2), channel_b.last_received_frame());
+    }
+
+    #[test]
+    fn rchannel_sync_lost_packet_is_recovered_by_next_packet() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        let first = RMercuryInputWrapper::new(TestInput::new(1, 1), 0);
+        let second = RMercuryInputWrapper::new(TestInput::new(1, 2), 1);
+
+        history.queue(&[first]);
+        history.confirm(0);
+        channel_a.sync(&history, Instant::now());
+
+        // Drop the first packet
+        channel_b.transport.poll();
+
+        history.queue(&[second]);
+        history.confirm(1);
+        channel_a.sync(&history, Instant::now());
+
+        let actual = channel_b.sync(&RInputHistory::new(), Instant::now());
+        assert_eq!(vec![first, second], actual);
+        assert_eq!(Some(1), channel_b.last_received_frame());
+    }
+
+    #[test]
+    fn rchannel_sync_checksums_of_lost_packets_are_resent_until_acked() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+        let checksums: Vec<RFrameChecksum> = (0..3)
+            .map(|frame| RFrameChecksum {
+                frame: frame,
+                checksum: frame as u64 + 10,
+            })
+            .collect();
+
+        // A checksum every frame, but a packet only every other frame, and the first one is lost
+        channel_a.add_local_checksum(checksums[0]);
+        history.confirm(0);
+        channel_a.sync(&history, Instant::now());
+        channel_b.transport.poll();
+
+        channel_a.add_local_checksum(checksums[1]);
+        channel_a.add_local_checksum(checksums[2]);
+        history.confirm(2);
+        channel_a.sync(&history, Instant::now());
+
+        channel_b.sync(&RInputHistory::new(), Instant::now());
+        assert_eq!(checksums, channel_b.take_remote_checksums());
+
+        channel_a.sync(&history, Instant::now());
+        assert_eq!(true, channel_a.local_checksums.is_empty());
+    }
+
+    /// An input whose payload is large enough for a few frames of it to fill a packet.
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct LargeInput {
+        player_id: usize,
+        value: u8,
+    }
+
+    impl RMercuryInput for LargeInput {
+        fn get_player_id(&self) -> usize {
+            return self.player_id;
+        }
+
+        fn set_player_id(&mut self, player_id: usize) {
+            self.player_id = player_id;
+        }
+
+        fn to_bits(&self) -> Vec<u8> {
+            return vec![self.value; 100];
+        }
+
+        fn from_bits(bytes: Vec<u8>) -> Result<Self, RMercuryError> {
+            return match bytes.first() {
+                Some(value) => Ok(Self {
+                    player_id: 0,
+                    value: *value,
+                }),
+                None => Err(RMercuryError::InputMalformed),
+            };
+        }
+    }
+
+    /// A memory transport that fails the test on any datagram larger than an input packet may be.
+    struct SizeCheckedTransport {
+        transport: RMercuryMemoryTransport,
+    }
+
+    impl RMercuryTransport for SizeCheckedTransport {
+        fn send(&mut self, datagram: &[u8]) -> std::io::Result<()> {
+            assert_eq!(true, datagram.len() <= MAX_INPUT_PACKET_SIZE);
+            return self.transport.send(datagram);
+        }
+
+        fn poll(&mut self) -> Vec<Vec<u8>> {
+            return self.transport.poll();
+        }
+    }
+
+    #[test]
+    fn rchannel_sync_large_inputs_are_sent_in_packets_that_fit_a_datagram() {
+        const FRAMES: usize = 40;
+
+        let (transport_a, transport_b) = RMercuryMemoryTransport::pair();
+        let transport_a = SizeCheckedTransport {
+            transport: transport_a,
+        };
+        let mut channel_a =
+            RChannel::new(transport_a, SESSION_ID, test_config(), test_claims(), None);
+        let mut channel_b =
+            RChannel::new(transport_b, SESSION_ID, test_config(), test_claims(), None);
+
+        let mut history = RInputHistory::<LargeInput>::new();
+        while channel_a.sync_state() == RSyncState::Synchronizing
+            || channel_b.sync_state() == RSyncState::Synchronizing
+        {
+            channel_a.sync(&history, Instant::now());
+            channel_b.sync(&history, Instant::now());
+        }
+
+        // Fill the send window with far more inputs than fit in a single packet
+        for frame in 0..FRAMES {
+            for player_id in 1..=2 {
+                let input = LargeInput {
+                    player_id: player_id,
+                    value: frame as u8,
+                };
+                history.queue(&[RMercuryInputWrapper::new(input, frame)]);
+            }
+        }
+        history.confirm(FRAMES - 1);
+
+        let mut received = vec![];
+        let mut now = Instant::now();
+        for _ in 0..FRAMES {
+            channel_a.sync(&history, now);
+            received.append(&mut channel_b.sync(&RInputHistory::new(), now));
+            now += Duration::milliseconds(RESEND_INTERVAL_MILLISECONDS);
+        }
+
+        assert_eq!(FRAMES * 2, received.len());
+        assert_eq!(Some(FRAMES - 1), channel_b.last_received_frame());
+    }
+
+    #[test]
+    fn rchannel_sync_duplicate_frames_are_only_returned_once() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        let first = RMercuryInputWrapper::new(TestInput::new(1, 1), 0);
+        let second = RMercuryInputWrapper::new(TestInput::new(1, 2), 1);
+
+        history.queue(&[first]);
+        history.confirm(0);
+        channel_a.sync(&history, Instant::now());
+        assert_eq!(
+            vec![first],
+            channel_b.sync(&RInputHistory::new(), Instant::now())
+        );
+
+        history.queue(&[second]);
+        history.confirm(1);
+        channel_a.sync(&history, Instant::now());
+        assert_eq!(
+            vec![second],
+            channel_b.sync(&RInputHistory::new(), Instant::now())
+        );
+    }
+
+    #[test]
+    fn rchannel_sync_nothing_confirmed_sends_nothing() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+
+        channel_a.sync(&RInputHistory::new(), Instant::now());
+
+        let actual = channel_b.sync(&RInputHistory::new(), Instant::now());
+        assert_eq!(true, actual.is_empty());
+        assert_eq!(None, channel_b.last_received_frame());
+    }
+
+    #[test]
+    fn rchannel_sync_handshake_synchronizes_both_peers() {
+        let (channel_a, channel_b) = channel_pair();
+
+        assert_eq!(RSyncState::Synchronized, channel_a.sync_state());
+        assert_eq!(RSyncState::Synchronized, channel_b.sync_state());
+        assert_eq!(true, channel_a.round_trip_time().is_some());
+        assert_eq!(true, channel_b.round_trip_time().is_some());
+    }
+
+    #[test]
+    fn rchannel_sync_handshake_other_config_fails() {
+        let (transport_a, transport_b) = RMercuryMemoryTransport::pair();
+        let mut remote_config = test_config();
+        remote_config.sim_executions_per_second = 30;
+
+        let mut channel_a = RChannel::<TestInput, _>::new(
+            transport_a,
+            SESSION_ID,
+            test_config(),
+            test_claims(),
+            None,
+        );
+        let mut channel_b = RChannel::<TestInput, _>::new(
+            transport_b,
+            SESSION_ID,
+            remote_config,
+            test_claims(),
+            None,
+        );
+
+        let history = RInputHistory::new();
+        for _ in 0..3 {
+            channel_a.sync(&history, Instant::now());
+            channel_b.sync(&history, Instant::now());
+        }
+
+        let expected = RMercuryError::ConfigMismatch {
+            local: test_config(),
+            remote: remote_config,
+        };
+        assert_eq!(RSyncState::Failed(expected), channel_a.sync_state());
+    }
+
+    #[test]
+    fn rchannel_sync_other_session_drops_packet() {
+        let (transport_a, transport_b) = RMercuryMemoryTransport::pair();
+        let mut channel_a = RChannel::<TestInput, _>::new(
+            transport_a,
+            SESSION_ID,
+            test_config(),
+            test_claims(),
+            None,
+        );
+        let mut channel_b = RChannel::<TestInput, _>::new(
+            transport_b,
+            SESSION_ID + 1,
+            test_config(),
+            test_claims(),
+            None,
+        );
+        let mut history = RInputHistory::new();
+
+        history.queue(&[RMercuryInputWrapper::new(TestInput::new(1, 7), 3)]);
+        history.confirm(3);
+        channel_a.sync(&history, Instant::now());
+
+        assert_eq!(
+            true,
+            channel_b
+                .sync(&RInputHistory::new(), Instant::now())
+                .is_empty()
+        );
+        assert_eq!(None, channel_b.last_received_frame());
+    }
+
+    #[test]
+    fn rchannel_sync_received_frames_are_acked_to_sender() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        history.confirm(3);
+        channel_a.sync(&history, Instant::now());
+        assert_eq!(0, channel_a.acked_local_frame);
+
+        channel_b.sync(&RInputHistory::new(), Instant::now());
+        assert_eq!(Some(3), channel_b.last_received_frame());
+
+        channel_a.sync(&history, Instant::now());
+        assert_eq!(4, channel_a.acked_local_frame);
+    }
+
+    #[test]
+    fn rchannel_sync_acked_frames_are_not_resent() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        history.confirm(3);
+        channel_a.sync(&history, Instant::now());
+        channel_b.sync(&RInputHistory::new(), Instant::now());
+        channel_a.sync(&history, Instant::now());
+
+        history.queue(&[RMercuryInputWrapper::new(TestInput::new(1, 7), 4)]);
+        history.confirm(4);
+        channel_a.sync(&history, Instant::now());
+
+        let datagrams = channel_b.transport.poll();
+        assert_eq!(1, datagrams.len());
+
+        let packet = match RPacket::<TestInput>::from_bits(&datagrams[0], SESSION_ID) {
+            Ok(RPacket::Input(packet)) => packet,
+            packet => panic!("Expected an input packet, got {:?}", packet),
+        };
+        assert_eq!(4, packet.start_frame);
+        assert_eq!(vec![vec![TestInput::new(1, 7)]], packet.frames);
+    }
+
+    #[test]
+    fn rchannel_sync_nothing_new_does_not_send_again() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        history.confirm(3);
+        channel_a.sync(&history, Instant::now());
+        channel_a.sync(&history, Instant::now());
+
+        assert_eq!(1, channel_b.transport.poll().len());
+    }
+
+    #[test]
+    fn rchannel_advance_frame_ahead_of_peer_recommends_delay() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        // Channel a's clock runs three times as fast as channel b's
+        for frame in 0..60 {
+            history.confirm(frame * 3);
+            channel_a.advance_frame(frame * 3);
+            channel_b.advance_frame(frame);
+            channel_a.sync(&history, Instant::now());
+            channel_b.sync(&RInputHistory::new(), Instant::now());
+        }
+
+        assert_eq!(true, channel_a.recommended_frame_delay() > 0);
+        assert_eq!(0, channel_b.recommended_frame_delay());
+    }
+
+    #[test]
+    fn rchannel_advance_frame_peers_in_step_recommend_no_delay() {
+        let (mut channel_a, mut channel_b) = channel_pair();
+        let mut history = RInputHistory::new();
+
+        for frame in 0..60 {
+            history.confirm(frame);
+            channel_a.advance_frame(frame);
+            channel_b.advance_frame(frame);
+            channel_a.sync(&history, Instant::now());
+            channel_b.sync(&history, Instant::now());
+        }
+
+        assert_eq!(0, channel_a.recommended_frame_delay());
+        assert_eq!(0, channel_b.recommended_frame_delay());
+    }
+
+    #[test]
+    fn rchannel_manager_execute_trims_acked_history() {
+        let (mut manager, mut remote) = manager_pair(SESSION_ID, test_config());
+
+        manager.queue_local_input(&[RMercuryInputWrapper::new(TestInput::new(1, 7), 2)]);
+        manager.confirm_local_frame(3);
+        manager.execute(Instant::now());
+        assert_eq!(0, manager.local_history.packet_since(0, 0).start_frame);
+
+        remote.sync(&RInputHistory::new(), Instant::now());
+        manager.execute(Instant::now());
+
+        let packet = manager.local_history.packet_since(0, 0);
+        assert_eq!(4, packet.start_frame);
+        assert_eq!(true, packet.frames.is_empty());
+        assert_eq!(Some(3), manager.local_history.last_frame());
+    }
+
+    #[test]
+    fn rchannel_manager_remote_inputs_missing_frame_is_predicted() {
+        let (mut manager, mut remote) = manager_pair(SESSION_ID, test_config());
+
+        let mut history = RInputHistory::new();
+        history.queue(&[RMercuryInputWrapper::new(TestInput::new(2, 7), 0)]);
+        history.confirm(0);
+        remote.sync(&history, Instant::now());
+        manager.execute(Instant::now());
+
+        assert_eq!(vec![TestInput::new(2, 7)], manager.remote_inputs(0));
+        assert_eq!(vec![TestInput::new(2, 7)], manager.remote_inputs(1));
+    }
+
+    #[test]
+    fn rchannel_manager_execute_wrong_prediction_returns_incorrect_frame() {
+        let (mut manager, mut remote) = manager_pair(SESSION_ID, test_config());
+
+        manager.remote_inputs(1);
+        manager.remote_inputs(2);
+
+        let mut history = RInputHistory::new();
+        history.queue(&[RMercuryInputWrapper::new(TestInput::new(2, 7), 1)]);
+        history.confirm(2);
+        remote.sync(&history, Instant::now());
+
+        assert_eq!(Some(1), manager.execute(Instant::now()));
+        assert_eq!(None, manager.execute(Instant::now()));
+        assert_eq!(vec![TestInput::new(2, 7)], manager.remote_inputs(1));
+    }
+
+    #[test]
+    fn rchannel_manager_execute_correct_prediction_returns_none() {
+        let (mut manager, mut remote) = manager_pair(SESSION_ID, test_config());
+
+        manager.remote_inputs(0);
+        manager.remote_inputs(1);
+
+        let mut history = RInputHistory::new();
+        history.confirm(1);
+        remote.sync(&history, Instant::now());
+
+        assert_eq!(None, manager.execute(Instant::now()));
+    }
+
+    #[test]
+    fn rchannel_manager_is_frame_confirmed_waits_for_every_player() {
+        let (mut manager, mut remote) = manager_pair(SESSION_ID, test_config());
+
+        manager.confirm_local_frame(1);
+        assert_eq!(false, manager.is_frame_confirmed(0));
+
+        let mut history = RInputHistory::new();
+        history.confirm(2);
+        remote.sync(&history, Instant::now());
+        manager.execute(Instant::now());
+
+        assert_eq!(true, manager.is_frame_confirmed(1));
+        assert_eq!(false, manager.is_frame_confirmed(2));
+    }
+
+    #[test]
+    fn rchannel_manager_execute_without_desync_detection_drops_remote_checksums() {
+        let (mut manager, mut remote) = manager_pair(SESSION_ID, test_config());
+
+        let mut history = RInputHistory::new();
+        history.confirm(0);
+        remote.add_local_checksum(RFrameChecksum {
+            frame: 0,
+            checksum: 7,
+        });
+        remote.sync(&history, Instant::now());
+        manager.execute(Instant::now());
+
+        assert_eq!(true, manager.remote_checksums[0].is_empty());
+    }
+
+    #[test]
+    fn rchannel_manager_execute_remote_checksums_ahead_are_limited_to_history() {
+        let mut config = test_config();
+        config.desync_detection_interval = 1;
+
+        let (mut manager, mut remote) = manager_pair(SESSION_ID, config);
+
+        // The peer checksums frames the local session has not confirmed any of
+        let mut history = RInputHistory::new();
+        for frame in 0..MAX_CHECKSUM_HISTORY * 2 {
+            remote.add_local_checksum(RFrameChecksum {
+                frame: frame,
+                checksum: 7,
+            });
+            history.confirm(frame);
+            remote.sync(&history, Instant::now());
+            manager.execute(Instant::now());
+        }
+
+        let remote_checksums = &manager.remote_checksums[0];
+        assert_eq!(MAX_CHECKSUM_HISTORY, remote_checksums.len());
+        assert_eq!(MAX_CHECKSUM_HISTORY, remote_checksums[0].frame);
+    }
 }
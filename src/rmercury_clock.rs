@@ -0,0 +1,78 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use time::{Duration, Instant};
+
+/// The trait a clock must implement to drive the timing of a RMercury session.
+pub trait RMercuryClock {
+    /// Get the current time.
+    fn now(&self) -> Instant;
+}
+
+/// Clock that follows the system's monotonic time. Used by default.
+pub struct RMercurySystemClock;
+
+impl RMercuryClock for RMercurySystemClock {
+    fn now(&self) -> Instant {
+        return Instant::now();
+    }
+}
+
+/// Clock that only moves when it is advanced. Useful for tests, headless servers and tools that fast-forward simulations without waiting. Clones share the same time.
+#[derive(Clone)]
+pub struct RMercuryManualClock {
+    start: Instant,
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl RMercuryManualClock {
+    /// Create a new clock, stopped at the current system time.
+    pub fn new() -> Self {
+        return Self {
+            start: Instant::now(),
+            elapsed: Rc::new(Cell::new(Duration::zero())),
+        };
+    }
+
+    /// Move the clock forward by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.set(self.elapsed.get() + duration);
+    }
+}
+
+impl Default for RMercuryManualClock {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl RMercuryClock for RMercuryManualClock {
+    fn now(&self) -> Instant {
+        return self.start + self.elapsed.get();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rmercury_manual_clock_now_only_moves_when_advanced() {
+        let clock = RMercuryManualClock::new();
+        let start = clock.now();
+
+        assert_eq!(start, clock.now());
+
+        clock.advance(Duration::milliseconds(5));
+        assert_eq!(start + Duration::milliseconds(5), clock.now());
+    }
+
+    #[test]
+    fn rmercury_manual_clock_clones_share_time() {
+        let clock = RMercuryManualClock::new();
+        let other = clock.clone();
+
+        clock.advance(Duration::seconds(1));
+
+        assert_eq!(clock.now(), other.now());
+    }
+}
@@ -0,0 +1,471 @@
+use super::*;
+use rmercury_io::{
+    invalid_data, read_string, read_u16, read_u32, read_u64, read_u8, to_u16, to_u8, write_string,
+};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The bytes every diagnostics bundle starts with.
+const DIAGNOSTICS_MAGIC: &[u8; 4] = b"RMDG";
+
+/// The version of the diagnostics bundle format. Bump whenever the layout changes.
+const DIAGNOSTICS_VERSION: u8 = 4;
+
+/// A self-contained snapshot of a session, written when a desync or SyncTest mismatch is detected. Two bundles of the same session can be compared to find the first frame they diverged on, and the recorded frames can be replayed from the initial state.
+///
+/// Bundles use the following layout, with all integers little endian:
+///
+/// | Size | Field                      |
+/// |------|----------------------------|
+/// | 4    | Magic, "RMDG"              |
+/// | 1    | Format version             |
+/// | 4    | Session id                 |
+/// | 4    | Number of players          |
+/// | 4    | Sim executions per second  |
+/// | 4    | Desync detection interval  |
+/// | 1    | Local player id count      |
+/// | n    | Local player ids, as u16   |
+/// | 4    | Detected frame             |
+/// | n    | Reason                     |
+/// | 4    | Initial state length       |
+/// | n    | Initial state              |
+/// | 4    | Frame count                |
+/// | n    | Frames                     |
+///
+/// Each frame is the frame as a u32, the checksum as a u64, an input count as a u8 followed by each input as [player id: u16][payload length: u16][payload], and the logged game state. Strings are a u32 length followed by UTF-8 bytes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RMercuryDiagnostics {
+    /// The id of the session
+    pub session_id: u32,
+    /// The config of the session
+    pub config: RMercurySessionConfig,
+    /// The ids of the local players of the session that wrote the bundle
+    pub local_player_ids: Vec<usize>,
+    /// The frame the problem was detected on
+    pub detected_frame: usize,
+    /// What was detected
+    pub reason: String,
+    /// The serialized game state the first recorded frame was simulated from, from `RMercuryGameInterface::game_state_to_bits`
+    pub initial_state: Vec<u8>,
+    /// The most recent confirmed frames, oldest first
+    pub frames: Vec<RMercuryDiagnosticsFrame>,
+}
+
+/// A confirmed frame recorded in a diagnostics bundle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RMercuryDiagnosticsFrame {
+    /// The frame that was simulated
+    pub frame: usize,
+    /// The inputs the frame was simulated with
+    pub inputs: Vec<RMercuryDiagnosticsInput>,
+    /// The checksum of the game state after simulating the frame
+    pub checksum: u64,
+    /// The logged game state after simulating the frame
+    pub log: String,
+}
+
+/// A serialized input recorded in a diagnostics bundle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RMercuryDiagnosticsInput {
+    pub player_id: usize,
+    pub payload: Vec<u8>,
+}
+
+/// The first frame two diagnostics bundles differ on, along with what each recorded for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RMercuryDivergence {
+    pub frame: usize,
+    pub this: RMercuryDiagnosticsFrame,
+    pub other: RMercuryDiagnosticsFrame,
+}
+
+impl RMercuryDiagnosticsFrame {
+    /// Create a new frame.
+    pub fn new(
+        frame: usize,
+        inputs: Vec<RMercuryDiagnosticsInput>,
+        checksum: u64,
+        log: String,
+    ) -> Self {
+        return Self {
+            frame: frame,
+            inputs: inputs,
+            checksum: checksum,
+            log: log,
+        };
+    }
+}
+
+impl RMercuryDiagnostics {
+    /// Find the first frame both bundles recorded but differ on, either in their inputs or their checksum.
+    pub fn first_divergence(&self, other: &RMercuryDiagnostics) -> Option<RMercuryDivergence> {
+        for frame in self.frames.iter() {
+            let other_frame = match other.frames.iter().find(|f| f.frame == frame.frame) {
+                Some(other_frame) => other_frame,
+                None => continue,
+            };
+
+            if frame.inputs != other_frame.inputs || frame.checksum != other_frame.checksum {
+                return Some(RMercuryDivergence {
+                    frame: frame.frame,
+                    this: frame.clone(),
+                    other: other_frame.clone(),
+                });
+            }
+        }
+
+        return None;
+    }
+
+    /// Write the bundle to a file, replacing it if it exists.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+
+        return writer.flush();
+    }
+
+    /// Load a bundle from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        return Self::read(&mut reader);
+    }
+
+    /// Serialize the bundle to the writer. Returns an error if a count or length does not fit in its field.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(DIAGNOSTICS_MAGIC)?;
+        writer.write_all(&[DIAGNOSTICS_VERSION])?;
+        writer.write_all(&self.session_id.to_le_bytes())?;
+        writer.write_all(&(self.config.number_of_players as u32).to_le_bytes())?;
+        writer.write_all(&(self.config.sim_executions_per_second as u32).to_le_bytes())?;
+        writer.write_all(&(self.config.desync_detection_interval as u32).to_le_bytes())?;
+
+        writer.write_all(&[to_u8(self.local_player_ids.len())?])?;
+        for player_id in self.local_player_ids.iter() {
+            writer.write_all(&(*player_id as u16).to_le_bytes())?;
+        }
+
+        writer.write_all(&(self.detected_frame as u32).to_le_bytes())?;
+        write_string(writer, &self.reason)?;
+        writer.write_all(&(self.initial_state.len() as u32).to_le_bytes())?;
+        writer.write_all(&self.initial_state)?;
+
+        writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+        for frame in self.frames.iter() {
+            writer.write_all(&(frame.frame as u32).to_le_bytes())?;
+            writer.write_all(&frame.checksum.to_le_bytes())?;
+
+            writer.write_all(&[to_u8(frame.inputs.len())?])?;
+            for input in frame.inputs.iter() {
+                writer.write_all(&(input.player_id as u16).to_le_bytes())?;
+                writer.write_all(&to_u16(input.payload.len())?.to_le_bytes())?;
+                writer.write_all(&input.payload)?;
+            }
+
+            write_string(writer, &frame.log)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Deserialize a bundle from the reader. Returns an error if it is not a bundle, or was written with a format version this build does not understand.
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != *DIAGNOSTICS_MAGIC {
+            return Err(invalid_data("Not a diagnostics bundle"));
+        }
+
+        if read_u8(reader)? != DIAGNOSTICS_VERSION {
+            return Err(invalid_data("Unsupported diagnostics bundle version"));
+        }
+
+        let session_id = read_u32(reader)?;
+        let config = RMercurySessionConfig {
+            number_of_players: read_u32(reader)? as usize,
+            sim_executions_per_second: read_u32(reader)? as usize,
+            desync_detection_interval: read_u32(reader)? as usize,
+        };
+
+        let player_id_count = read_u8(reader)? as usize;
+        let mut local_player_ids = Vec::with_capacity(player_id_count);
+        for _ in 0..player_id_count {
+            local_player_ids.push(read_u16(reader)? as usize);
+        }
+
+        let detected_frame = read_u32(reader)? as usize;
+        let reason = read_string(reader)?;
+        let mut initial_state = vec![0; read_u32(reader)? as usize];
+        reader.read_exact(&mut initial_state)?;
+
+        let frame_count = read_u32(reader)? as usize;
+        let mut frames = vec![];
+        for _ in 0..frame_count {
+            let frame = read_u32(reader)? as usize;
+            let checksum = read_u64(reader)?;
+
+            let input_count = read_u8(reader)? as usize;
+            let mut inputs = Vec::with_capacity(input_count);
+            for _ in 0..input_count {
+                let player_id = read_u16(reader)? as usize;
+                let mut payload = vec![0; read_u16(reader)? as usize];
+                reader.read_exact(&mut payload)?;
+
+                inputs.push(RMercuryDiagnosticsInput {
+                    player_id: player_id,
+                    payload: payload,
+                });
+            }
+
+            let log = read_string(reader)?;
+            frames.push(RMercuryDiagnosticsFrame::new(frame, inputs, checksum, log));
+        }
+
+        return Ok(Self {
+            session_id: session_id,
+            config: config,
+            local_player_ids: local_player_ids,
+            detected_frame: detected_frame,
+            reason: reason,
+            initial_state: initial_state,
+            frames: frames,
+        });
+    }
+}
+
+impl fmt::Display for RMercuryDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "First divergent frame: {}", self.frame)?;
+        writeln!(f, "This inputs: {:?}", self.this.inputs)?;
+        writeln!(f, "Other inputs: {:?}", self.other.inputs)?;
+        writeln!(f, "This checksum: {:016x}", self.this.checksum)?;
+        writeln!(f, "Other checksum: {:016x}", self.other.checksum)?;
+        writeln!(f, "This game state:\n{}", self.this.log)?;
+        write!(f, "Other game state:\n{}", self.other.log)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmercury_test_game::{synchronized_pair, test_config, TestGame, TestInput};
+    use time::Duration;
+
+    fn frame(frame: usize, value: u8, checksum: u64) -> RMercuryDiagnosticsFrame {
+        let inputs = vec![
+            RMercuryDiagnosticsInput {
+                player_id: 1,
+                payload: vec![value, 7],
+            },
+            RMercuryDiagnosticsInput {
+                player_id: 2,
+                payload: vec![value],
+            },
+        ];
+
+        return RMercuryDiagnosticsFrame::new(frame, inputs, checksum, format!("frame {}", frame));
+    }
+
+    fn diagnostics(frames: Vec<RMercuryDiagnosticsFrame>) -> RMercuryDiagnostics {
+        return RMercuryDiagnostics {
+            session_id: 11,
+            config: test_config(),
+            local_player_ids: vec![1],
+            detected_frame: 30,
+            reason: String::from("Desync detected"),
+            initial_state: vec![1, 2, 3],
+            frames: frames,
+        };
+    }
+
+    #[test]
+    fn rmercury_diagnostics_write_read_round_trip() {
+        let expected = diagnostics(vec![frame(4, 1, 100), frame(5, 2, u64::MAX)]);
+
+        let mut bits = vec![];
+        expected.write(&mut bits).unwrap();
+        let actual = RMercuryDiagnostics::read(&mut bits.as_slice()).unwrap();
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn rmercury_diagnostics_read_other_magic_returns_error() {
+        let mut bits = vec![];
+        diagnostics(vec![]).write(&mut bits).unwrap();
+        bits[0] = b'X';
+
+        let error = RMercuryDiagnostics::read(&mut bits.as_slice()).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+
+    #[test]
+    fn rmercury_diagnostics_write_too_long_payload_returns_error() {
+        let mut too_long = frame(4, 1, 100);
+        too_long.inputs[0].payload = vec![0; u16::MAX as usize + 1];
+
+        let mut bits = vec![];
+        let error = diagnostics(vec![too_long]).write(&mut bits).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+    }
+
+    #[test]
+    fn rmercury_diagnostics_read_truncated_returns_error() {
+        let mut bits = vec![];
+        diagnostics(vec![frame(4, 1, 100)])
+            .write(&mut bits)
+            .unwrap();
+        bits.pop();
+
+        assert_eq!(
+            true,
+            RMercuryDiagnostics::read(&mut bits.as_slice()).is_err()
+        );
+    }
+
+    #[test]
+    fn rmercury_diagnostics_first_divergence_identical_returns_none() {
+        let this = diagnostics(vec![frame(4, 1, 100), frame(5, 2, 101)]);
+        let other = diagnostics(vec![frame(5, 2, 101), frame(6, 3, 102)]);
+
+        assert_eq!(None, this.first_divergence(&other));
+    }
+
+    #[test]
+    fn rmercury_diagnostics_first_divergence_returns_first_differing_frame() {
+        let this = diagnostics(vec![frame(4, 1, 100), frame(5, 2, 101), frame(6, 3, 102)]);
+        let other = diagnostics(vec![frame(5, 2, 201), frame(6, 4, 202)]);
+
+        let expected = RMercuryDivergence {
+            frame: 5,
+            this: frame(5, 2, 101),
+            other: frame(5, 2, 201),
+        };
+
+        assert_eq!(Some(expected), this.first_divergence(&other));
+    }
+
+    #[test]
+    fn rmercury_diverged_sessions_write_comparable_diagnostics() {
+        const DIVERGE_FRAME: usize = 20;
+
+        let directory = std::env::temp_dir().join(format!(
+            "rmercury-session-diagnostics-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| {
+                builder
+                    .with_desync_detection_interval(5)
+                    .with_diagnostics_directory(directory.clone())
+            },
+        );
+
+        for frame in 0..60 {
+            clock.advance(Duration::milliseconds(1));
+
+            if session_b.get_current_tick() == DIVERGE_FRAME {
+                // Something outside of the simulation changes the game state of session b
+                session_b.get_game_interface_mut().state.total += 1;
+            }
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, frame as u8)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, 2)]);
+            session_a.execute();
+            session_b.execute();
+        }
+
+        let diagnostics_a = RMercuryDiagnostics::load(session_a.get_diagnostics_file().unwrap());
+        let diagnostics_b = RMercuryDiagnostics::load(session_b.get_diagnostics_file().unwrap());
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let diagnostics_a = diagnostics_a.unwrap();
+        let diagnostics_b = diagnostics_b.unwrap();
+        let divergence = diagnostics_a.first_divergence(&diagnostics_b).unwrap();
+
+        assert_eq!(DIVERGE_FRAME, diagnostics_a.detected_frame);
+        assert_eq!(false, diagnostics_a.frames.is_empty());
+        assert_eq!(DIVERGE_FRAME, divergence.frame);
+        assert_eq!(divergence.this.inputs, divergence.other.inputs);
+        assert_eq!(
+            session_a.get_local_player_id(0),
+            diagnostics_a.local_player_ids.first().copied()
+        );
+    }
+
+    #[test]
+    fn rmercury_diagnostics_frames_replay_from_initial_state() {
+        const DIVERGE_FRAME: usize = 30;
+
+        let directory = std::env::temp_dir().join(format!(
+            "rmercury-replayable-diagnostics-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(1),
+            &mut game_a,
+            &mut game_b,
+            |_, builder| {
+                builder
+                    .with_desync_detection_interval(5)
+                    .with_diagnostics_directory(directory.clone())
+                    .with_diagnostics_frames(10)
+            },
+        );
+
+        for frame in 0..60 {
+            clock.advance(Duration::milliseconds(1));
+
+            if session_b.get_current_tick() == DIVERGE_FRAME {
+                session_b.get_game_interface_mut().state.total += 1;
+            }
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, frame as u8)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, 3)]);
+            session_a.execute();
+            session_b.execute();
+        }
+
+        let diagnostics = RMercuryDiagnostics::load(session_a.get_diagnostics_file().unwrap());
+        std::fs::remove_dir_all(&directory).unwrap();
+        let diagnostics = diagnostics.unwrap();
+
+        let mut game = TestGame::from_bits(&diagnostics.initial_state);
+        assert_eq!(10, diagnostics.frames.len());
+        assert_eq!(diagnostics.frames[0].frame, game.state.frame);
+        for frame in diagnostics.frames.iter() {
+            let inputs = frame
+                .inputs
+                .iter()
+                .map(|input| {
+                    let mut game_input = TestInput::from_bits(input.payload.clone()).unwrap();
+                    game_input.set_player_id(input.player_id);
+                    return game_input;
+                })
+                .collect();
+            game.advance_frame(inputs);
+
+            assert_eq!(frame.checksum, game.checksum());
+        }
+    }
+}
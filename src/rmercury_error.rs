@@ -0,0 +1,38 @@
+use super::*;
+
+/// Errors that RMercury can produce.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RMercuryError {
+    /// The packet ended before all of its fields could be read.
+    PacketTruncated,
+    /// The packet contained fields that contradict each other.
+    PacketMalformed,
+    /// The packet contained more bytes than its fields describe.
+    PacketTooLong,
+    /// A count or length was too large for its field, so the packet could not be written.
+    PacketFieldOverflow,
+    /// The packet was written with a protocol version this build does not understand.
+    UnsupportedProtocolVersion(u8),
+    /// The packet belongs to a different session.
+    SessionMismatch { expected: u32, actual: u32 },
+    /// The packet type is not known to this build.
+    UnknownPacketType(u8),
+    /// The game could not deserialize the payload of an input.
+    InputMalformed,
+    /// A remote peer was configured differently than the local session.
+    ConfigMismatch {
+        local: RMercurySessionConfig,
+        remote: RMercurySessionConfig,
+    },
+    /// A remote peer claimed a different player id than it was added with.
+    PlayerIdMismatch {
+        expected: usize,
+        actual: Option<usize>,
+    },
+    /// The same player id was claimed twice, by the local session or any of the remote peers.
+    DuplicatePlayerId(usize),
+    /// A player id was claimed or negotiated that is outside of 1 to the number of players in the session, such as when more players joined than the session allows.
+    InvalidPlayerId(usize),
+    /// A SyncTest session simulated a frame differently after rolling it back. The logged game states are available through the session.
+    SyncTestMismatch { frame: usize },
+}
@@ -0,0 +1,33 @@
+use time::Duration;
+
+/// Notifications about the lifecycle of a session, retrieved through `RMercury::poll_events`. Peers are identified by the order they were added to the builder, starting at 0.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RMercuryEvent {
+    /// The first packet was received from a remote peer.
+    ConnectedToPeer { peer: usize },
+    /// A round trip of the handshake with a remote peer completed.
+    SynchronizingWithPeer {
+        peer: usize,
+        count: usize,
+        total: usize,
+    },
+    /// The handshake with a remote peer completed.
+    SynchronizedWithPeer { peer: usize },
+    /// The handshake completed with every remote peer and the simulation started.
+    Running,
+    /// A remote peer has not sent anything for a while. Contains the time left before it is disconnected, or None without a disconnect timeout.
+    ConnectionInterrupted {
+        peer: usize,
+        disconnect_timeout: Option<Duration>,
+    },
+    /// A remote peer that was interrupted started sending again.
+    ConnectionResumed { peer: usize },
+    /// A remote peer timed out or was disconnected. Its players are simulated with the game's disconnected inputs from a frame agreed with the remaining peers.
+    DisconnectedFromPeer { peer: usize },
+    /// A player left or its peer was disconnected. From the frame on, every peer simulates the player with the game's disconnected inputs.
+    PlayerDisconnected { player_id: usize, frame: usize },
+    /// A remote peer simulated a confirmed frame to a different game state than the local session, so the sessions are no longer in sync.
+    DesyncDetected { peer: usize, frame: usize },
+    /// The session is running ahead of the remote players. Frames are lengthened until it catches up, but a game can also choose to wait.
+    TimeSync { frames_ahead: usize },
+}
@@ -6,9 +6,52 @@ pub trait RMercuryGameInterface<TGameState, TGameInput> {
     /// Log the game state. Used for debugging purposes.
     fn log_game_state(&self) -> String;
 
-    /// Advance the frame with the given inputs.
+    /// Advance the frame with the given inputs, ordered by player id.
     fn advance_frame(&mut self, inputs: Vec<TGameInput>);
 
     /// Retrieve the current game state.
     fn current_game_state(&self) -> TGameState;
+
+    /// The inputs of a player that left or whose peer disconnected, such as a neutral input or a disconnected flag. Defaults to no inputs.
+    fn disconnected_inputs(&self, _player_id: usize) -> Vec<TGameInput> {
+        return vec![];
+    }
+
+    /// Serialize the current game state, written at the start of replays. Defaults to no bytes, for games that always start from the same state.
+    fn game_state_to_bits(&self) -> Vec<u8> {
+        return vec![];
+    }
+
+    /// A checksum of the current game state, used to detect non-deterministic simulations and desyncs. Defaults to a hash of the logged game state; override it with a cheaper one where possible.
+    fn checksum(&self) -> u64 {
+        return fnv1a_hash(self.log_game_state().as_bytes());
+    }
+}
+
+/// Hash the bytes with the 64-bit FNV-1a hash.
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for byte in bytes.iter() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_hash_empty_returns_offset_basis() {
+        assert_eq!(0xcbf29ce484222325, fnv1a_hash(&[]));
+    }
+
+    #[test]
+    fn fnv1a_hash_known_value() {
+        assert_eq!(0xaf63dc4c8601ec8c, fnv1a_hash("a".as_bytes()));
+        assert_eq!(0x85944171f73967e8, fnv1a_hash("foobar".as_bytes()));
+    }
 }
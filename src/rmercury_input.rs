@@ -1,3 +1,5 @@
+use super::*;
+
 /// Trait required to link up game input to a form that RMercury can utilize.
 pub trait RMercuryInput {
     /// Get the player id that the input maps to.
@@ -9,10 +11,13 @@ pub trait RMercuryInput {
     /// Serialize the input to bits.
     fn to_bits(&self) -> Vec<u8>;
 
-    /// Deserialize the input from bits.
-    fn from_bits(bytes: Vec<u8>) -> Self;
+    /// Deserialize the input from bits. The bits come from remote peers and replays, so return `RMercuryError::InputMalformed` for any that could not have been serialized by `to_bits` instead of panicking.
+    fn from_bits(bytes: Vec<u8>) -> Result<Self, RMercuryError>
+    where
+        Self: Sized;
 }
 
+/// An input paired with the frame it will be executed on.
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct RMercuryInputWrapper<TGameInput>
 where
@@ -40,21 +45,7 @@ where
     }
 
     /// Get the player id that the input maps to.
-    fn get_player_id(&self) -> usize {
+    pub fn get_player_id(&self) -> usize {
         return self.input.get_player_id();
     }
-
-    /// Serialize the input to bits.
-    fn to_bits(&self) -> Vec<u8> {
-        let input_bits = self.input.to_bits();
-        //TODO: add in frame
-        unimplemented!();
-    }
-
-    /// Deserialize the input from bits.
-    fn from_bits(bytes: Vec<u8>) -> Self {
-        let frame = 0; //TODO: deserialize frame
-        let input_bits = false; //TODO: deserialize inputs;
-        unimplemented!();
-    }
 }
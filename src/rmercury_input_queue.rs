@@ -0,0 +1,212 @@
+use super::*;
+use std::collections::{BTreeMap, VecDeque};
+
+/// The inputs received from a single remote peer. Frames that have not been received yet are predicted, and the predictions are kept so they can be checked once the real inputs arrive.
+pub struct RInputQueue<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    first_frame: usize,
+    confirmed_frames: VecDeque<Vec<TGameInput>>,
+    last_confirmed_inputs: Vec<TGameInput>,
+    predicted_frames: BTreeMap<usize, Vec<TGameInput>>,
+    first_incorrect_frame: Option<usize>,
+    disconnect_frame: Option<usize>,
+}
+
+impl<TGameInput> RInputQueue<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    pub fn new() -> Self {
+        return Self {
+            first_frame: 0,
+            confirmed_frames: VecDeque::new(),
+            last_confirmed_inputs: vec![],
+            predicted_frames: BTreeMap::new(),
+            first_incorrect_frame: None,
+            disconnect_frame: None,
+        };
+    }
+
+    /// Get the frame after the last confirmed frame.
+    fn next_frame(&self) -> usize {
+        return self.first_frame + self.confirmed_frames.len();
+    }
+
+    /// Add the confirmed inputs for the next frame. If the frame was predicted and the prediction differs from the confirmed inputs, it is marked as incorrect.
+    pub fn add_confirmed(&mut self, inputs: Vec<TGameInput>) {
+        let frame = self.next_frame();
+
+        if let Some(predicted_inputs) = self.predicted_frames.remove(&frame) {
+            if predicted_inputs != inputs {
+                self.mark_incorrect(frame);
+            }
+        }
+
+        self.last_confirmed_inputs = inputs.clone();
+        self.confirmed_frames.push_back(inputs);
+    }
+
+    /// Get the inputs for the given frame. Returns the confirmed inputs if they were received, otherwise predicts them.
+    pub fn inputs(
+        &mut self,
+        frame: usize,
+        predictor: &dyn RMercuryInputPredictor<TGameInput>,
+    ) -> Vec<TGameInput> {
+        if frame < self.first_frame {
+            panic!("Inputs for frame {} were already discarded!", frame);
+        }
+
+        if let Some(disconnect_frame) = self.disconnect_frame {
+            if frame >= disconnect_frame {
+                return vec![];
+            }
+        }
+
+        if frame < self.next_frame() {
+            return self.confirmed_frames[frame - self.first_frame].clone();
+        }
+
+        let predicted_inputs = predictor.predict(&self.last_confirmed_inputs, frame);
+        self.predicted_frames
+            .insert(frame, predicted_inputs.clone());
+
+        return predicted_inputs;
+    }
+
+    /// Stop using the peer's inputs from the given frame on. Those frames have no inputs, so they are marked as incorrect in case they were already simulated.
+    pub fn disconnect(&mut self, frame: usize) {
+        self.disconnect_frame = Some(frame);
+        self.predicted_frames.clear();
+
+        while self.next_frame() > frame && !self.confirmed_frames.is_empty() {
+            self.confirmed_frames.pop_back();
+        }
+
+        self.mark_incorrect(frame);
+    }
+
+    /// Get and reset the earliest frame whose prediction did not match the confirmed inputs.
+    pub fn take_first_incorrect_frame(&mut self) -> Option<usize> {
+        return self.first_incorrect_frame.take();
+    }
+
+    /// Drop all confirmed frames before the given frame.
+    pub fn trim(&mut self, frame: usize) {
+        while self.first_frame < frame && !self.confirmed_frames.is_empty() {
+            self.confirmed_frames.pop_front();
+            self.first_frame += 1;
+        }
+    }
+
+    fn mark_incorrect(&mut self, frame: usize) {
+        self.first_incorrect_frame = match self.first_incorrect_frame {
+            Some(incorrect_frame) if incorrect_frame < frame => Some(incorrect_frame),
+            _ => Some(frame),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmercury_test_game::TestInput;
+
+    #[test]
+    fn rinput_queue_inputs_confirmed_frame_returns_confirmed_inputs() {
+        let mut queue = RInputQueue::new();
+        queue.add_confirmed(vec![TestInput::new(2, 1)]);
+        queue.add_confirmed(vec![TestInput::new(2, 2)]);
+
+        let actual = queue.inputs(0, &RMercuryRepeatLastInput);
+
+        assert_eq!(vec![TestInput::new(2, 1)], actual);
+    }
+
+    #[test]
+    fn rinput_queue_inputs_missing_frame_repeats_last_confirmed_inputs() {
+        let mut queue = RInputQueue::new();
+        queue.add_confirmed(vec![TestInput::new(2, 1)]);
+        queue.add_confirmed(vec![TestInput::new(2, 2)]);
+
+        let actual = queue.inputs(5, &RMercuryRepeatLastInput);
+
+        assert_eq!(vec![TestInput::new(2, 2)], actual);
+    }
+
+    #[test]
+    fn rinput_queue_inputs_uses_given_predictor() {
+        struct PredictNothing;
+        impl RMercuryInputPredictor<TestInput> for PredictNothing {
+            fn predict(&self, _: &[TestInput], _: usize) -> Vec<TestInput> {
+                return vec![];
+            }
+        }
+
+        let mut queue = RInputQueue::new();
+        queue.add_confirmed(vec![TestInput::new(2, 1)]);
+
+        let actual = queue.inputs(1, &PredictNothing);
+
+        assert_eq!(true, actual.is_empty());
+    }
+
+    #[test]
+    fn rinput_queue_add_confirmed_wrong_prediction_marks_incorrect_frame() {
+        let mut queue = RInputQueue::<TestInput>::new();
+        queue.add_confirmed(vec![TestInput::new(2, 1)]);
+        queue.inputs(1, &RMercuryRepeatLastInput);
+        queue.inputs(2, &RMercuryRepeatLastInput);
+        queue.inputs(3, &RMercuryRepeatLastInput);
+
+        queue.add_confirmed(vec![TestInput::new(2, 1)]);
+        queue.add_confirmed(vec![TestInput::new(2, 2)]);
+        queue.add_confirmed(vec![TestInput::new(2, 3)]);
+
+        assert_eq!(Some(2), queue.take_first_incorrect_frame());
+        assert_eq!(None, queue.take_first_incorrect_frame());
+    }
+
+    #[test]
+    fn rinput_queue_add_confirmed_correct_prediction_is_not_incorrect() {
+        let mut queue = RInputQueue::<TestInput>::new();
+        queue.add_confirmed(vec![TestInput::new(2, 1)]);
+        queue.inputs(1, &RMercuryRepeatLastInput);
+
+        queue.add_confirmed(vec![TestInput::new(2, 1)]);
+
+        assert_eq!(None, queue.take_first_incorrect_frame());
+    }
+
+    #[test]
+    fn rinput_queue_add_confirmed_unpredicted_frame_is_not_incorrect() {
+        let mut queue = RInputQueue::<TestInput>::new();
+
+        queue.add_confirmed(vec![]);
+
+        assert_eq!(None, queue.take_first_incorrect_frame());
+    }
+
+    #[test]
+    fn rinput_queue_disconnect_frames_from_disconnect_have_no_inputs() {
+        let mut queue = RInputQueue::new();
+        queue.add_confirmed(vec![TestInput::new(2, 1)]);
+        queue.add_confirmed(vec![TestInput::new(2, 2)]);
+        queue.inputs(2, &RMercuryRepeatLastInput);
+
+        queue.disconnect(1);
+
+        assert_eq!(Some(1), queue.take_first_incorrect_frame());
+        assert_eq!(true, queue.inputs(1, &RMercuryRepeatLastInput).is_empty());
+        assert_eq!(true, queue.inputs(2, &RMercuryRepeatLastInput).is_empty());
+        assert_eq!(
+            vec![TestInput::new(2, 1)],
+            queue.inputs(0, &RMercuryRepeatLastInput)
+        );
+    }
+}
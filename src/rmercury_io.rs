@@ -0,0 +1,65 @@
+//! Little endian helpers for the files RMercury reads and writes.
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+/// Create an error for data that could not be parsed.
+pub fn invalid_data(message: &str) -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidData, message);
+}
+
+/// Convert a count or length to a u8 field, failing if it does not fit.
+pub fn to_u8(value: usize) -> io::Result<u8> {
+    return u8::try_from(value).map_err(|_| too_large());
+}
+
+/// Convert a count or length to a u16 field, failing if it does not fit.
+pub fn to_u16(value: usize) -> io::Result<u16> {
+    return u16::try_from(value).map_err(|_| too_large());
+}
+
+/// Create an error for a count or length too large for its field.
+fn too_large() -> io::Error {
+    return io::Error::new(io::ErrorKind::InvalidInput, "Too large for its field");
+}
+
+/// Write a string as a u32 length followed by its UTF-8 bytes.
+pub fn write_string<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    writer.write_all(&(value.len() as u32).to_le_bytes())?;
+    return writer.write_all(value.as_bytes());
+}
+
+/// Read a string written by `write_string`.
+pub fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut bytes = vec![0; read_u32(reader)? as usize];
+    reader.read_exact(&mut bytes)?;
+
+    return String::from_utf8(bytes).map_err(|_| invalid_data("Invalid UTF-8 string"));
+}
+
+/// Read a little endian u8.
+pub fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    return Ok(bytes[0]);
+}
+
+/// Read a little endian u16.
+pub fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    return Ok(u16::from_le_bytes(bytes));
+}
+
+/// Read a little endian u32.
+pub fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    return Ok(u32::from_le_bytes(bytes));
+}
+
+/// Read a little endian u64.
+pub fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    return Ok(u64::from_le_bytes(bytes));
+}
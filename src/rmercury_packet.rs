@@ -0,0 +1,786 @@
+use super::*;
+use rmercury_sync::RPlayerClaim;
+use std::convert::TryFrom;
+
+/// The version of the wire format. Bump whenever the layout of a packet changes in a release.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// The number of bytes in the header every packet starts with.
+const PACKET_HEADER_SIZE: usize = 6;
+
+/// The number of bytes preceding the runs of an input packet.
+const INPUT_PACKET_HEADER_SIZE: usize = PACKET_HEADER_SIZE + 15;
+
+/// The number of bytes following the runs of an input packet without any peer statuses or checksums.
+const INPUT_PACKET_FOOTER_SIZE: usize = 6;
+
+/// The number of bytes preceding the payload of a single input in an input packet.
+const INPUT_PACKET_INPUT_HEADER_SIZE: usize = 4;
+
+/// The number of bytes of a single peer status in an input packet.
+const INPUT_PACKET_PEER_STATUS_SIZE: usize = 7;
+
+/// The number of bytes of a single checksum in an input packet.
+const INPUT_PACKET_CHECKSUM_SIZE: usize = 12;
+
+/// The largest input packet sent. Stays below the smallest MTU common on the internet, so packets are never fragmented.
+pub const MAX_INPUT_PACKET_SIZE: usize = 1200;
+
+/// The number of bytes preceding the player claims of a sync packet.
+const SYNC_PACKET_HEADER_SIZE: usize = PACKET_HEADER_SIZE + 11;
+
+/// The number of bytes of a single player claim in a sync packet.
+const SYNC_PACKET_CLAIM_SIZE: usize = 6;
+
+const PACKET_TYPE_INPUT: u8 = 0;
+const PACKET_TYPE_SYNC_REQUEST: u8 = 1;
+const PACKET_TYPE_SYNC_REPLY: u8 = 2;
+
+/// Any packet sent between peers.
+///
+/// Every packet starts with the same header, with all integers little endian:
+///
+/// | Offset | Size | Field                  |
+/// |--------|------|------------------------|
+/// | 0      | 1    | Protocol version       |
+/// | 1      | 4    | Session id             |
+/// | 5      | 1    | Packet type            |
+#[derive(Clone, Debug, PartialEq)]
+pub enum RPacket<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    /// Inputs and acknowledgements, sent once the session is running
+    Input(RInputPacket<TGameInput>),
+    /// A request for the peer to echo back the nonce, sent during the handshake
+    SyncRequest(RSyncPacket),
+    /// The echo of a sync request
+    SyncReply(RSyncPacket),
+}
+
+impl<TGameInput> RPacket<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    /// Serialize the packet to bits for the given session. Returns an error if a count or length does not fit in its field.
+    pub fn to_bits(&self, session_id: u32) -> Result<Vec<u8>, RMercuryError> {
+        return match self {
+            RPacket::Input(packet) => packet.to_bits(session_id),
+            RPacket::SyncRequest(packet) => packet.to_bits(session_id, PACKET_TYPE_SYNC_REQUEST),
+            RPacket::SyncReply(packet) => packet.to_bits(session_id, PACKET_TYPE_SYNC_REPLY),
+        };
+    }
+
+    /// Deserialize any packet from bits. Returns an error if the bits are malformed or belong to a different session.
+    pub fn from_bits(bytes: &[u8], session_id: u32) -> Result<Self, RMercuryError> {
+        let mut reader = PacketReader::new(bytes);
+        let packet_type = read_header(&mut reader, session_id)?;
+
+        let packet = match packet_type {
+            PACKET_TYPE_INPUT => RPacket::Input(RInputPacket::read_body(&mut reader)?),
+            PACKET_TYPE_SYNC_REQUEST => RPacket::SyncRequest(RSyncPacket::read_body(&mut reader)?),
+            PACKET_TYPE_SYNC_REPLY => RPacket::SyncReply(RSyncPacket::read_body(&mut reader)?),
+            packet_type => return Err(RMercuryError::UnknownPacketType(packet_type)),
+        };
+
+        if !reader.is_empty() {
+            return Err(RMercuryError::PacketTooLong);
+        }
+
+        return Ok(packet);
+    }
+}
+
+/// Write the header every packet starts with.
+fn write_header(bits: &mut Vec<u8>, session_id: u32, packet_type: u8) {
+    bits.push(PROTOCOL_VERSION);
+    bits.extend_from_slice(&session_id.to_le_bytes());
+    bits.push(packet_type);
+}
+
+/// Convert a count or length to a u8 field.
+fn to_u8(value: usize) -> Result<u8, RMercuryError> {
+    return u8::try_from(value).map_err(|_| RMercuryError::PacketFieldOverflow);
+}
+
+/// Convert a count or length to a u16 field.
+fn to_u16(value: usize) -> Result<u16, RMercuryError> {
+    return u16::try_from(value).map_err(|_| RMercuryError::PacketFieldOverflow);
+}
+
+/// Read the header every packet starts with, returning the packet type.
+fn read_header(reader: &mut PacketReader, session_id: u32) -> Result<u8, RMercuryError> {
+    let version = reader.read_u8()?;
+    if version != PROTOCOL_VERSION {
+        return Err(RMercuryError::UnsupportedProtocolVersion(version));
+    }
+
+    let packet_session_id = reader.read_u32()?;
+    if packet_session_id != session_id {
+        return Err(RMercuryError::SessionMismatch {
+            expected: session_id,
+            actual: packet_session_id,
+        });
+    }
+
+    return reader.read_u8();
+}
+
+/// A packet exchanged during the handshake. Requests carry a random nonce the peer echoes back in its reply, along with the config of the sender and the player ids its local players claim.
+///
+/// | Offset | Size | Field                      |
+/// |--------|------|----------------------------|
+/// | 0      | 6    | Header                     |
+/// | 6      | 4    | Nonce                      |
+/// | 10     | 2    | Number of players          |
+/// | 12     | 2    | Sim executions per second  |
+/// | 14     | 2    | Desync detection interval  |
+/// | 16     | 1    | Claim count                |
+/// | 17     | n    | Claims                     |
+///
+/// Each claim is a player id, 0 when unassigned, as a u16 followed by the player's seed as a u32.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RSyncPacket {
+    pub nonce: u32,
+    pub config: RMercurySessionConfig,
+    pub claims: Vec<RPlayerClaim>,
+}
+
+impl RSyncPacket {
+    pub fn new(nonce: u32, config: RMercurySessionConfig, claims: Vec<RPlayerClaim>) -> Self {
+        return Self {
+            nonce: nonce,
+            config: config,
+            claims: claims,
+        };
+    }
+
+    fn to_bits(&self, session_id: u32, packet_type: u8) -> Result<Vec<u8>, RMercuryError> {
+        let mut bits = Vec::with_capacity(
+            SYNC_PACKET_HEADER_SIZE + SYNC_PACKET_CLAIM_SIZE * self.claims.len(),
+        );
+        write_header(&mut bits, session_id, packet_type);
+        bits.extend_from_slice(&self.nonce.to_le_bytes());
+        bits.extend_from_slice(&(self.config.number_of_players as u16).to_le_bytes());
+        bits.extend_from_slice(&(self.config.sim_executions_per_second as u16).to_le_bytes());
+        bits.extend_from_slice(&(self.config.desync_detection_interval as u16).to_le_bytes());
+        bits.push(to_u8(self.claims.len())?);
+
+        for claim in self.claims.iter() {
+            bits.extend_from_slice(&(claim.player_id.unwrap_or(0) as u16).to_le_bytes());
+            bits.extend_from_slice(&claim.seed.to_le_bytes());
+        }
+
+        return Ok(bits);
+    }
+
+    fn read_body(reader: &mut PacketReader) -> Result<Self, RMercuryError> {
+        let nonce = reader.read_u32()?;
+        let config = RMercurySessionConfig {
+            number_of_players: reader.read_u16()? as usize,
+            sim_executions_per_second: reader.read_u16()? as usize,
+            desync_detection_interval: reader.read_u16()? as usize,
+        };
+
+        let claim_count = reader.read_u8()?;
+        let mut claims = Vec::with_capacity(claim_count as usize);
+        for _ in 0..claim_count {
+            let player_id = match reader.read_u16()? {
+                0 => None,
+                player_id => Some(player_id as usize),
+            };
+
+            claims.push(RPlayerClaim::new(player_id, reader.read_u32()?));
+        }
+
+        return Ok(Self::new(nonce, config, claims));
+    }
+}
+
+/// The longest run a single run header can describe.
+const MAX_RUN_LENGTH: usize = u8::MAX as usize;
+
+/// What the sender of an input packet knows about another player in the session.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RPeerStatus {
+    /// The player the status is about
+    pub player_id: usize,
+    /// The next frame the sender expects from the player. Once disconnected, the first frame the sender simulates without the player's inputs.
+    pub next_frame: usize,
+    /// Whether the sender has disconnected the player
+    pub disconnected: bool,
+    /// Whether the player left the session on purpose, on the frame in next_frame. Players that left are disconnected as well.
+    pub left: bool,
+}
+
+/// The checksum of the game state after simulating a confirmed frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RFrameChecksum {
+    /// The frame that was simulated
+    pub frame: usize,
+    /// The checksum of the game state after simulating the frame
+    pub checksum: u64,
+}
+
+/// A packet of inputs sent between peers. Carries the inputs of every frame the receiver has not acknowledged yet, so a single lost packet is recovered by the next one.
+///
+/// Packets use the following layout, with all integers little endian:
+///
+/// | Offset | Size | Field                  |
+/// |--------|------|------------------------|
+/// | 0      | 6    | Header                 |
+/// | 6      | 4    | Ack frame              |
+/// | 10     | 4    | Current frame          |
+/// | 14     | 1    | Frame advantage        |
+/// | 15     | 4    | Start frame            |
+/// | 19     | 2    | Frame count            |
+/// | 21     | n    | Runs                   |
+/// | n      | 1    | Peer status count      |
+/// | n      | n    | Peer statuses          |
+/// | n      | 4    | Checksum ack frame     |
+/// | n      | 1    | Checksum count         |
+/// | n      | n    | Checksums              |
+///
+/// Consecutive frames with identical inputs are compressed into a single run:
+///
+/// | Size | Field                                            |
+/// |------|--------------------------------------------------|
+/// | 1    | Number of frames the run covers                  |
+/// | 1    | Number of inputs per frame                       |
+/// | n    | Inputs, each as [player id: u16][payload length: u16][payload] |
+///
+/// Each peer status is the player id as a u16, the next frame as a u32 and the player's connection as a u8: 0 if connected, 1 if disconnected and 2 if the player left. Each checksum is the frame as a u32 followed by the checksum as a u64.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RInputPacket<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    /// The next frame the sender expects from the receiver. Every frame before it has been received.
+    pub ack_frame: usize,
+    /// The frame the sender is currently simulating
+    pub current_frame: usize,
+    /// How many frames the sender estimates it is ahead of the receiver
+    pub frame_advantage: i32,
+    /// The frame the first set of inputs is for
+    pub start_frame: usize,
+    /// The inputs for each consecutive frame
+    pub frames: Vec<Vec<TGameInput>>,
+    /// What the sender knows about the other players it is connected to
+    pub peer_statuses: Vec<RPeerStatus>,
+    /// The frame after the last checksum the sender received from the receiver. Every checksum before it has been received.
+    pub checksum_ack_frame: usize,
+    /// The checksums of the sender's checksummed confirmed frames the receiver has not acknowledged yet, oldest first
+    pub checksums: Vec<RFrameChecksum>,
+}
+
+impl<TGameInput> RInputPacket<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    pub fn new(
+        ack_frame: usize,
+        current_frame: usize,
+        frame_advantage: i32,
+        start_frame: usize,
+        frames: Vec<Vec<TGameInput>>,
+    ) -> Self {
+        return Self {
+            ack_frame: ack_frame,
+            current_frame: current_frame,
+            frame_advantage: frame_advantage,
+            start_frame: start_frame,
+            frames: frames,
+            peer_statuses: vec![],
+            checksum_ack_frame: 0,
+            checksums: vec![],
+        };
+    }
+
+    /// Drop frames from the end until the serialized packet fits in the given number of bytes. The first frame is always kept, so the receiver keeps making progress.
+    pub fn truncate_to_size(&mut self, max_size: usize) {
+        let mut size = INPUT_PACKET_HEADER_SIZE
+            + INPUT_PACKET_FOOTER_SIZE
+            + INPUT_PACKET_PEER_STATUS_SIZE * self.peer_statuses.len()
+            + INPUT_PACKET_CHECKSUM_SIZE * self.checksums.len();
+
+        // Each frame is sized as a run of its own, which is never smaller than the run it is compressed into.
+        let mut frame_count = 0;
+        for inputs in self.frames.iter() {
+            size += 2;
+            for input in inputs.iter() {
+                size += INPUT_PACKET_INPUT_HEADER_SIZE + input.to_bits().len();
+            }
+
+            if size > max_size && frame_count > 0 {
+                break;
+            }
+
+            frame_count += 1;
+        }
+
+        self.frames.truncate(frame_count);
+    }
+
+    /// Serialize the packet to bits for the given session. Returns an error if a count or length does not fit in its field.
+    pub fn to_bits(&self, session_id: u32) -> Result<Vec<u8>, RMercuryError> {
+        let mut bits = Vec::with_capacity(INPUT_PACKET_HEADER_SIZE + INPUT_PACKET_FOOTER_SIZE);
+        write_header(&mut bits, session_id, PACKET_TYPE_INPUT);
+        bits.extend_from_slice(&(self.ack_frame as u32).to_le_bytes());
+        bits.extend_from_slice(&(self.current_frame as u32).to_le_bytes());
+        bits.push(self.frame_advantage.max(i8::MIN as i32).min(i8::MAX as i32) as i8 as u8);
+        bits.extend_from_slice(&(self.start_frame as u32).to_le_bytes());
+        bits.extend_from_slice(&to_u16(self.frames.len())?.to_le_bytes());
+
+        let mut frame = 0;
+        while frame < self.frames.len() {
+            let inputs = &self.frames[frame];
+
+            let mut run_length = 1;
+            while frame + run_length < self.frames.len()
+                && run_length < MAX_RUN_LENGTH
+                && self.frames[frame + run_length] == *inputs
+            {
+                run_length += 1;
+            }
+
+            bits.push(to_u8(run_length)?);
+            bits.push(to_u8(inputs.len())?);
+            for input in inputs.iter() {
+                let payload = input.to_bits();
+                bits.extend_from_slice(&(input.get_player_id() as u16).to_le_bytes());
+                bits.extend_from_slice(&to_u16(payload.len())?.to_le_bytes());
+                bits.extend_from_slice(&payload);
+            }
+
+            frame += run_length;
+        }
+
+        bits.push(to_u8(self.peer_statuses.len())?);
+        for peer_status in self.peer_statuses.iter() {
+            bits.extend_from_slice(&(peer_status.player_id as u16).to_le_bytes());
+            bits.extend_from_slice(&(peer_status.next_frame as u32).to_le_bytes());
+            bits.push(match (peer_status.disconnected, peer_status.left) {
+                (_, true) => 2,
+                (true, false) => 1,
+                (false, false) => 0,
+            });
+        }
+
+        bits.extend_from_slice(&(self.checksum_ack_frame as u32).to_le_bytes());
+        bits.push(to_u8(self.checksums.len())?);
+        for checksum in self.checksums.iter() {
+            bits.extend_from_slice(&(checksum.frame as u32).to_le_bytes());
+            bits.extend_from_slice(&checksum.checksum.to_le_bytes());
+        }
+
+        return Ok(bits);
+    }
+
+    fn read_body(reader: &mut PacketReader) -> Result<Self, RMercuryError> {
+        let ack_frame = reader.read_u32()? as usize;
+        let current_frame = reader.read_u32()? as usize;
+        let frame_advantage = reader.read_u8()? as i8 as i32;
+        let start_frame = reader.read_u32()? as usize;
+        let frame_count = reader.read_u16()? as usize;
+
+        let mut frames = Vec::with_capacity(frame_count);
+        while frames.len() < frame_count {
+            let run_length = reader.read_u8()? as usize;
+            if run_length == 0 || frames.len() + run_length > frame_count {
+                return Err(RMercuryError::PacketMalformed);
+            }
+
+            let input_count = reader.read_u8()? as usize;
+            let mut inputs = Vec::with_capacity(input_count);
+            for _ in 0..input_count {
+                let player_id = reader.read_u16()? as usize;
+                let payload_length = reader.read_u16()? as usize;
+                let payload = reader.read_bytes(payload_length)?;
+
+                let mut input = TGameInput::from_bits(payload.to_vec())?;
+                input.set_player_id(player_id);
+                inputs.push(input);
+            }
+
+            for _ in 0..run_length {
+                frames.push(inputs.clone());
+            }
+        }
+
+        let peer_status_count = reader.read_u8()? as usize;
+        let mut peer_statuses = Vec::with_capacity(peer_status_count);
+        for _ in 0..peer_status_count {
+            let player_id = reader.read_u16()? as usize;
+            let next_frame = reader.read_u32()? as usize;
+            let (disconnected, left) = match reader.read_u8()? {
+                0 => (false, false),
+                1 => (true, false),
+                2 => (true, true),
+                _ => return Err(RMercuryError::PacketMalformed),
+            };
+
+            peer_statuses.push(RPeerStatus {
+                player_id: player_id,
+                next_frame: next_frame,
+                disconnected: disconnected,
+                left: left,
+            });
+        }
+
+        let checksum_ack_frame = reader.read_u32()? as usize;
+        let checksum_count = reader.read_u8()? as usize;
+        let mut checksums = Vec::with_capacity(checksum_count);
+        for _ in 0..checksum_count {
+            checksums.push(RFrameChecksum {
+                frame: reader.read_u32()? as usize,
+                checksum: reader.read_u64()?,
+            });
+        }
+
+        let mut packet = Self::new(
+            ack_frame,
+            current_frame,
+            frame_advantage,
+            start_frame,
+            frames,
+        );
+        packet.peer_statuses = peer_statuses;
+        packet.checksum_ack_frame = checksum_ack_frame;
+        packet.checksums = checksums;
+
+        return Ok(packet);
+    }
+}
+
+/// Reads little endian values from a packet, returning an error instead of reading past the end.
+struct PacketReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PacketReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        return Self {
+            bytes: bytes,
+            offset: 0,
+        };
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], RMercuryError> {
+        if self.bytes.len() < self.offset + length {
+            return Err(RMercuryError::PacketTruncated);
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + length];
+        self.offset += length;
+
+        return Ok(bytes);
+    }
+
+    fn read_u8(&mut self) -> Result<u8, RMercuryError> {
+        let bytes = self.read_bytes(1)?;
+        return Ok(bytes[0]);
+    }
+
+    fn read_u16(&mut self) -> Result<u16, RMercuryError> {
+        let bytes = self.read_bytes(2)?;
+        return Ok(u16::from_le_bytes([bytes[0], bytes[1]]));
+    }
+
+    fn read_u32(&mut self) -> Result<u32, RMercuryError> {
+        let bytes = self.read_bytes(4)?;
+        return Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+    }
+
+    fn read_u64(&mut self) -> Result<u64, RMercuryError> {
+        let bytes = self.read_bytes(8)?;
+        let mut le_bytes = [0; 8];
+        le_bytes.copy_from_slice(bytes);
+
+        return Ok(u64::from_le_bytes(le_bytes));
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.offset == self.bytes.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmercury_test_game::{test_config, TestInput};
+
+    const SESSION_ID: u32 = 11;
+
+    fn read_input_packet(
+        bytes: &[u8],
+        session_id: u32,
+    ) -> Result<RInputPacket<TestInput>, RMercuryError> {
+        return match RPacket::from_bits(bytes, session_id)? {
+            RPacket::Input(packet) => Ok(packet),
+            _ => Err(RMercuryError::PacketMalformed),
+        };
+    }
+
+    fn frames(values: &[u8]) -> Vec<Vec<TestInput>> {
+        return values
+            .iter()
+            .map(|value| match value {
+                0 => vec![],
+                value => vec![TestInput::new(1, *value)],
+            })
+            .collect();
+    }
+
+    #[test]
+    fn rinput_packet_to_bits_from_bits_round_trips() {
+        let expected = RInputPacket::new(9, 12, -3, 42, frames(&[0, 0, 3, 3, 3, 0, 5, 0]));
+
+        let bits = expected.to_bits(SESSION_ID).unwrap();
+        let actual = read_input_packet(&bits, SESSION_ID);
+
+        assert_eq!(Ok(expected), actual);
+    }
+
+    #[test]
+    fn rinput_packet_to_bits_too_many_inputs_per_frame_returns_error() {
+        let packet = RInputPacket::new(0, 0, 0, 0, vec![vec![TestInput::new(1, 3); 256]]);
+
+        assert_eq!(
+            Err(RMercuryError::PacketFieldOverflow),
+            packet.to_bits(SESSION_ID)
+        );
+    }
+
+    #[test]
+    fn rinput_packet_to_bits_from_bits_peer_statuses_round_trip() {
+        let mut expected = RInputPacket::new(9, 12, 0, 42, frames(&[3]));
+        expected.peer_statuses = vec![
+            RPeerStatus {
+                player_id: 2,
+                next_frame: 40,
+                disconnected: false,
+                left: false,
+            },
+            RPeerStatus {
+                player_id: 3,
+                next_frame: 17,
+                disconnected: true,
+                left: false,
+            },
+            RPeerStatus {
+                player_id: 4,
+                next_frame: 23,
+                disconnected: true,
+                left: true,
+            },
+        ];
+
+        let bits = expected.to_bits(SESSION_ID).unwrap();
+        let actual = read_input_packet(&bits, SESSION_ID);
+
+        assert_eq!(Ok(expected), actual);
+    }
+
+    #[test]
+    fn rinput_packet_to_bits_from_bits_checksums_round_trip() {
+        let mut expected = RInputPacket::new(9, 12, 0, 42, frames(&[3]));
+        expected.checksum_ack_frame = 27;
+        expected.checksums = vec![
+            RFrameChecksum {
+                frame: 30,
+                checksum: 0xcbf29ce484222325,
+            },
+            RFrameChecksum {
+                frame: 31,
+                checksum: 7,
+            },
+        ];
+
+        let bits = expected.to_bits(SESSION_ID).unwrap();
+        let actual = read_input_packet(&bits, SESSION_ID);
+
+        assert_eq!(Ok(expected), actual);
+    }
+
+    #[test]
+    fn rinput_packet_from_bits_checksum_count_past_end_returns_error() {
+        let mut bits = RInputPacket::new(9, 12, 0, 42, frames(&[]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+        let last = bits.len() - 1;
+        bits[last] = 2;
+
+        assert_eq!(
+            Err(RMercuryError::PacketTruncated),
+            read_input_packet(&bits, SESSION_ID)
+        );
+    }
+
+    #[test]
+    fn rinput_packet_to_bits_frame_advantage_is_clamped() {
+        let bits = RInputPacket::new(0, 5, -300, 0, frames(&[]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+        let actual = read_input_packet(&bits, SESSION_ID).unwrap();
+
+        assert_eq!(5, actual.current_frame);
+        assert_eq!(i8::MIN as i32, actual.frame_advantage);
+    }
+
+    #[test]
+    fn rinput_packet_to_bits_repeated_inputs_are_compressed() {
+        let single = RInputPacket::new(0, 0, 0, 0, frames(&[3]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+        let repeated = RInputPacket::new(0, 0, 0, 0, frames(&[3; 200]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+
+        assert_eq!(
+            INPUT_PACKET_HEADER_SIZE + 7 + INPUT_PACKET_FOOTER_SIZE,
+            single.len()
+        );
+        assert_eq!(single.len(), repeated.len());
+    }
+
+    #[test]
+    fn rinput_packet_to_bits_long_runs_are_split() {
+        let expected = RInputPacket::new(0, 0, 0, 0, frames(&[0; 600]));
+
+        let bits = expected.to_bits(SESSION_ID).unwrap();
+        let actual = read_input_packet(&bits, SESSION_ID);
+
+        assert_eq!(
+            INPUT_PACKET_HEADER_SIZE + 3 * 2 + INPUT_PACKET_FOOTER_SIZE,
+            bits.len()
+        );
+        assert_eq!(Ok(expected), actual);
+    }
+
+    #[test]
+    fn rinput_packet_from_bits_truncated_returns_error() {
+        let bits = RInputPacket::new(3, 0, 0, 7, frames(&[1, 1, 2]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+
+        for length in 0..bits.len() {
+            let actual = read_input_packet(&bits[..length], SESSION_ID);
+            assert_eq!(Err(RMercuryError::PacketTruncated), actual);
+        }
+    }
+
+    #[test]
+    fn rinput_packet_truncate_to_size_drops_frames_past_size() {
+        let mut packet = RInputPacket::new(3, 0, 0, 7, frames(&[1, 2, 3, 4]));
+        packet.checksums = vec![RFrameChecksum {
+            frame: 2,
+            checksum: 5,
+        }];
+
+        // Room for two frames of a single input each
+        packet.truncate_to_size(INPUT_PACKET_HEADER_SIZE + 2 * 7 + INPUT_PACKET_FOOTER_SIZE + 12);
+
+        assert_eq!(frames(&[1, 2]), packet.frames);
+        assert_eq!(
+            INPUT_PACKET_HEADER_SIZE + 2 * 7 + INPUT_PACKET_FOOTER_SIZE + 12,
+            packet.to_bits(SESSION_ID).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn rinput_packet_truncate_to_size_keeps_first_frame() {
+        let mut packet = RInputPacket::new(3, 0, 0, 7, frames(&[1, 2]));
+
+        packet.truncate_to_size(0);
+
+        assert_eq!(frames(&[1]), packet.frames);
+    }
+
+    #[test]
+    fn rinput_packet_from_bits_run_past_frame_count_returns_error() {
+        let mut bits = RInputPacket::new(3, 0, 0, 7, frames(&[1, 1]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+        bits[INPUT_PACKET_HEADER_SIZE] = 3;
+
+        let actual = read_input_packet(&bits, SESSION_ID);
+        assert_eq!(Err(RMercuryError::PacketMalformed), actual);
+    }
+
+    #[test]
+    fn rinput_packet_from_bits_malformed_input_returns_error() {
+        let mut bits = RInputPacket::new(3, 0, 0, 7, frames(&[1]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+
+        // A payload of two bytes, which the test input can not have been serialized to
+        let payload_length = INPUT_PACKET_HEADER_SIZE + 4;
+        bits[payload_length..payload_length + 2].copy_from_slice(&2u16.to_le_bytes());
+        bits.insert(payload_length + 3, 0);
+
+        let actual = read_input_packet(&bits, SESSION_ID);
+        assert_eq!(Err(RMercuryError::InputMalformed), actual);
+    }
+
+    #[test]
+    fn rpacket_to_bits_from_bits_sync_packets_round_trip() {
+        let config = test_config();
+        let request = RPacket::<TestInput>::SyncRequest(RSyncPacket::new(
+            0xDEAD_BEEF,
+            config,
+            vec![RPlayerClaim::new(Some(2), 17), RPlayerClaim::new(None, 3)],
+        ));
+        let reply = RPacket::<TestInput>::SyncReply(RSyncPacket::new(
+            0xDEAD_BEEF,
+            config,
+            vec![RPlayerClaim::new(None, 42)],
+        ));
+
+        let request_bits = request.to_bits(SESSION_ID).unwrap();
+        assert_eq!(
+            SYNC_PACKET_HEADER_SIZE + SYNC_PACKET_CLAIM_SIZE * 2,
+            request_bits.len()
+        );
+        assert_eq!(Ok(request), RPacket::from_bits(&request_bits, SESSION_ID));
+        assert_eq!(
+            Ok(reply.clone()),
+            RPacket::from_bits(&reply.to_bits(SESSION_ID).unwrap(), SESSION_ID)
+        );
+    }
+
+    #[test]
+    fn rpacket_from_bits_unknown_type_returns_error() {
+        let mut bits = RInputPacket::new(3, 0, 0, 7, frames(&[1]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+        bits[PACKET_HEADER_SIZE - 1] = 9;
+
+        let actual = RPacket::<TestInput>::from_bits(&bits, SESSION_ID);
+        assert_eq!(Err(RMercuryError::UnknownPacketType(9)), actual);
+    }
+
+    #[test]
+    fn rinput_packet_from_bits_other_session_returns_error() {
+        let bits = RInputPacket::new(3, 0, 0, 7, frames(&[1]))
+            .to_bits(SESSION_ID)
+            .unwrap();
+
+        let actual = read_input_packet(&bits, SESSION_ID + 1);
+        assert_eq!(
+            Err(RMercuryError::SessionMismatch {
+                expected: SESSION_ID + 1,
+                actual: SESSION_ID
+            }),
+            actual
+        );
+    }
+}
@@ -0,0 +1,17 @@
+/// Trait used to predict a remote peer's inputs for frames that have not been received yet.
+pub trait RMercuryInputPredictor<TGameInput> {
+    /// Predict the inputs for the given frame, using the inputs of the last frame received from the peer.
+    fn predict(&self, last_confirmed_inputs: &[TGameInput], frame: usize) -> Vec<TGameInput>;
+}
+
+/// The default predictor. Assumes the remote peer keeps repeating the inputs of the last frame received.
+pub struct RMercuryRepeatLastInput;
+
+impl<TGameInput> RMercuryInputPredictor<TGameInput> for RMercuryRepeatLastInput
+where
+    TGameInput: Copy,
+{
+    fn predict(&self, last_confirmed_inputs: &[TGameInput], _frame: usize) -> Vec<TGameInput> {
+        return last_confirmed_inputs.to_vec();
+    }
+}
@@ -0,0 +1,360 @@
+use super::*;
+use rmercury_io::{invalid_data, read_u16, read_u32, read_u8, to_u16, to_u8};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// The bytes every replay starts with.
+const REPLAY_MAGIC: &[u8; 4] = b"RMRP";
+
+/// The version of the replay format. Bump whenever the layout changes.
+const REPLAY_VERSION: u8 = 3;
+
+/// A recording of a whole match, read back from the confirmed inputs a session streamed as it ran.
+///
+/// Replays use the following layout, with all integers little endian:
+///
+/// | Size | Field                      |
+/// |------|----------------------------|
+/// | 4    | Magic, "RMRP"              |
+/// | 1    | Format version             |
+/// | 4    | Session id                 |
+/// | 4    | Number of players          |
+/// | 4    | Sim executions per second  |
+/// | 4    | Desync detection interval  |
+/// | 4    | Initial state length       |
+/// | n    | Initial state              |
+/// | n    | Frames, until the end      |
+///
+/// Frames start at frame 0 and follow each other without gaps. Each frame is an input count as a u8, followed by each input as [player id: u16][payload length: u16][payload].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RMercuryReplay<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    /// The id of the session
+    pub session_id: u32,
+    /// The config of the session
+    pub config: RMercurySessionConfig,
+    /// The game state the match started from, from `RMercuryGameInterface::game_state_to_bits`
+    pub initial_state: Vec<u8>,
+    /// The inputs of each frame, in the order they were simulated with
+    pub frames: Vec<Vec<TGameInput>>,
+}
+
+impl<TGameInput> RMercuryReplay<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    /// Load a replay from a file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        return Self::read(&mut reader);
+    }
+
+    /// Deserialize a replay from the reader. Returns an error if it is not a replay, was written with a format version this build does not understand, or ends in the middle of a frame.
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != *REPLAY_MAGIC {
+            return Err(invalid_data("Not a replay"));
+        }
+
+        if read_u8(reader)? != REPLAY_VERSION {
+            return Err(invalid_data("Unsupported replay version"));
+        }
+
+        let session_id = read_u32(reader)?;
+        let config = RMercurySessionConfig {
+            number_of_players: read_u32(reader)? as usize,
+            sim_executions_per_second: read_u32(reader)? as usize,
+            desync_detection_interval: read_u32(reader)? as usize,
+        };
+
+        let mut initial_state = vec![0; read_u32(reader)? as usize];
+        reader.read_exact(&mut initial_state)?;
+
+        let mut frames = vec![];
+        loop {
+            // The replay ends wherever the session stopped recording.
+            let mut input_count = [0; 1];
+            if reader.read(&mut input_count)? == 0 {
+                break;
+            }
+
+            let mut inputs = Vec::with_capacity(input_count[0] as usize);
+            for _ in 0..input_count[0] {
+                let player_id = read_u16(reader)? as usize;
+                let mut payload = vec![0; read_u16(reader)? as usize];
+                reader.read_exact(&mut payload)?;
+
+                let mut input =
+                    TGameInput::from_bits(payload).map_err(|_| invalid_data("Malformed input"))?;
+                input.set_player_id(player_id);
+                inputs.push(input);
+            }
+
+            frames.push(inputs);
+        }
+
+        return Ok(Self {
+            session_id: session_id,
+            config: config,
+            initial_state: initial_state,
+            frames: frames,
+        });
+    }
+}
+
+/// Streams the inputs of each confirmed frame to a writer as the match runs.
+pub struct RReplayWriter<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    writer: Box<dyn Write>,
+    next_frame: usize,
+    phantom_input: PhantomData<TGameInput>,
+}
+
+impl<TGameInput> RReplayWriter<TGameInput>
+where
+    TGameInput: RMercuryInput,
+    TGameInput: Copy,
+    TGameInput: PartialEq,
+{
+    /// Start a replay on the writer, writing the header with the session config and the state the match starts from.
+    pub fn new(
+        mut writer: Box<dyn Write>,
+        session_id: u32,
+        config: RMercurySessionConfig,
+        initial_state: Vec<u8>,
+    ) -> io::Result<Self> {
+        writer.write_all(REPLAY_MAGIC)?;
+        writer.write_all(&[REPLAY_VERSION])?;
+        writer.write_all(&session_id.to_le_bytes())?;
+        writer.write_all(&(config.number_of_players as u32).to_le_bytes())?;
+        writer.write_all(&(config.sim_executions_per_second as u32).to_le_bytes())?;
+        writer.write_all(&(config.desync_detection_interval as u32).to_le_bytes())?;
+        writer.write_all(&(initial_state.len() as u32).to_le_bytes())?;
+        writer.write_all(&initial_state)?;
+
+        return Ok(Self {
+            writer: writer,
+            next_frame: 0,
+            phantom_input: PhantomData,
+        });
+    }
+
+    /// The next frame the replay expects. Every frame before it has been written.
+    pub fn next_frame(&self) -> usize {
+        return self.next_frame;
+    }
+
+    /// Write the inputs of the next frame. Returns an error without writing anything if there are more inputs, or a longer payload, than the format can hold.
+    pub fn write_frame(&mut self, inputs: &[TGameInput]) -> io::Result<()> {
+        let mut bits = vec![to_u8(inputs.len())?];
+        for input in inputs.iter() {
+            let payload = input.to_bits();
+            bits.extend_from_slice(&(input.get_player_id() as u16).to_le_bytes());
+            bits.extend_from_slice(&to_u16(payload.len())?.to_le_bytes());
+            bits.extend_from_slice(&payload);
+        }
+
+        self.writer.write_all(&bits)?;
+        self.next_frame += 1;
+
+        return Ok(());
+    }
+
+    /// Flush everything written so far to the writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        return self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmercury_test_game::{
+        synchronized_pair, test_config, TestGame, TestInput, TestWriter,
+    };
+    use time::Duration;
+
+    #[test]
+    fn rreplay_writer_write_frame_read_round_trip() {
+        let frames = vec![
+            vec![],
+            vec![TestInput::new(1, 4)],
+            vec![TestInput::new(2, 5), TestInput::new(1, 6)],
+        ];
+
+        let writer = TestWriter::new();
+        let mut replay =
+            RReplayWriter::new(Box::new(writer.clone()), 11, test_config(), vec![1, 2, 3]).unwrap();
+        for inputs in frames.iter() {
+            replay.write_frame(inputs).unwrap();
+        }
+        let bits = writer.bits.borrow().clone();
+
+        let expected = RMercuryReplay {
+            session_id: 11,
+            config: test_config(),
+            initial_state: vec![1, 2, 3],
+            frames: frames,
+        };
+
+        assert_eq!(3, replay.next_frame());
+        assert_eq!(
+            expected,
+            RMercuryReplay::read(&mut bits.as_slice()).unwrap()
+        );
+    }
+
+    #[test]
+    fn rreplay_writer_write_frame_too_many_inputs_writes_nothing() {
+        let writer = TestWriter::new();
+        let mut replay =
+            RReplayWriter::new(Box::new(writer.clone()), 11, test_config(), vec![]).unwrap();
+        let header_length = writer.bits.borrow().len();
+
+        let error = replay
+            .write_frame(&[TestInput::new(1, 4); 256])
+            .unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+        assert_eq!(0, replay.next_frame());
+        assert_eq!(header_length, writer.bits.borrow().len());
+    }
+
+    #[test]
+    fn rmercury_replay_read_other_magic_returns_error() {
+        let writer = TestWriter::new();
+        RReplayWriter::<TestInput>::new(Box::new(writer.clone()), 11, test_config(), vec![])
+            .unwrap();
+        let mut bits = writer.bits.borrow().clone();
+        bits[0] = b'X';
+
+        let error = RMercuryReplay::<TestInput>::read(&mut bits.as_slice()).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+
+    #[test]
+    fn rmercury_replay_read_partial_frame_returns_error() {
+        let writer = TestWriter::new();
+        let mut replay =
+            RReplayWriter::new(Box::new(writer.clone()), 11, test_config(), vec![]).unwrap();
+        replay.write_frame(&[TestInput::new(1, 4)]).unwrap();
+        let mut bits = writer.bits.borrow().clone();
+        bits.pop();
+
+        assert_eq!(
+            true,
+            RMercuryReplay::<TestInput>::read(&mut bits.as_slice()).is_err()
+        );
+    }
+
+    #[test]
+    fn rmercury_replay_recorded_session_replays_to_same_states() {
+        const FRAMES: usize = 30;
+
+        let writer = TestWriter::new();
+        let mut game = TestGame::new();
+        let mut builder = RMercuryBuilder::new(&mut game)
+            .with_clock(RMercuryManualClock::new())
+            .with_replay_writer(writer.clone());
+
+        let mut session = builder.build();
+        let mut states = vec![session.get_game_state()];
+        for frame in 0..FRAMES {
+            session.add_local_input(0, &mut vec![TestInput::new(0, frame as u8)]);
+            assert_eq!(1, session.step(1));
+            states.push(session.get_game_state());
+        }
+        assert_eq!(true, session.finish_replay().is_ok());
+
+        let replay =
+            RMercuryReplay::<TestInput>::read(&mut writer.bits.borrow().as_slice()).unwrap();
+        let mut replay_game = TestGame::new();
+        for inputs in replay.frames.iter() {
+            replay_game.advance_frame(inputs.clone());
+        }
+
+        assert_eq!(TestGame::new().game_state_to_bits(), replay.initial_state);
+        assert_eq!(FRAMES, replay.frames.len());
+        assert_eq!(
+            states[replay.frames.len()],
+            replay_game.current_game_state()
+        );
+    }
+
+    #[test]
+    fn rmercury_replay_finish_replay_stops_recording() {
+        let writer = TestWriter::new();
+        let mut game = TestGame::new();
+        let mut builder = RMercuryBuilder::new(&mut game)
+            .with_clock(RMercuryManualClock::new())
+            .with_replay_writer(writer.clone());
+
+        let mut session = builder.build();
+        assert_eq!(10, session.step(10));
+        assert_eq!(true, session.finish_replay().is_ok());
+
+        let recorded = writer.bits.borrow().len();
+        assert_eq!(10, session.step(10));
+
+        assert_eq!(recorded, writer.bits.borrow().len());
+    }
+
+    #[test]
+    fn rmercury_replays_of_both_sessions_reach_identical_state() {
+        const FRAMES: usize = 40;
+
+        let writers = [TestWriter::new(), TestWriter::new()];
+        let clock = RMercuryManualClock::new();
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        let (mut session_a, mut session_b) = synchronized_pair(
+            &clock,
+            Duration::milliseconds(2),
+            &mut game_a,
+            &mut game_b,
+            |index, builder| builder.with_replay_writer(writers[index].clone()),
+        );
+
+        for frame in 0..FRAMES {
+            clock.advance(Duration::milliseconds(1));
+
+            session_a.add_local_input(0, &mut vec![TestInput::new(0, (frame * 2) as u8)]);
+            session_b.add_local_input(0, &mut vec![TestInput::new(0, (frame * 3 + 1) as u8)]);
+            session_a.execute();
+            session_b.execute();
+        }
+
+        let replay_a = RMercuryReplay::<TestInput>::read(&mut writers[0].bits.borrow().as_slice());
+        let replay_b = RMercuryReplay::<TestInput>::read(&mut writers[1].bits.borrow().as_slice());
+        let replay_a = replay_a.unwrap();
+        let replay_b = replay_b.unwrap();
+
+        // Replay the frames both sessions have confirmed
+        let frames = replay_a.frames.len().min(replay_b.frames.len());
+        let mut game_a = TestGame::new();
+        let mut game_b = TestGame::new();
+        for frame in 0..frames {
+            game_a.advance_frame(replay_a.frames[frame].clone());
+            game_b.advance_frame(replay_b.frames[frame].clone());
+        }
+
+        assert_eq!(true, frames > FRAMES / 2);
+        assert_eq!(replay_a.config, replay_b.config);
+        assert_eq!(game_a.current_game_state(), game_b.current_game_state());
+    }
+}
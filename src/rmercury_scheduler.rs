@@ -0,0 +1,188 @@
+use time::Instant;
+
+const NANOSECONDS_IN_SECOND: u64 = 1_000_000_000;
+
+/// The maximum number of frames to catch up on after the process hitches. Any time beyond it is dropped.
+const MAX_CATCH_UP_FRAMES: u64 = 5;
+
+/// While slowed down, each frame is lengthened by this fraction of a frame.
+const SLOW_DOWN_FRAME_DIVISOR: u64 = 8;
+
+/// A fixed timestep scheduler. Elapsed time is accumulated and spent one frame at a time.
+///
+/// Time is tracked in nanoseconds multiplied by the frame rate, so a frame always costs exactly one second worth of nanoseconds and no rounding error builds up.
+pub struct RFrameScheduler {
+    sim_executions_per_second: u64,
+    accumulated_time: u64,
+    last_update: Instant,
+    slow_down: bool,
+}
+
+impl RFrameScheduler {
+    /// Create a new scheduler for the given frame rate, starting with no accumulated time.
+    pub fn new(sim_executions_per_second: usize, now: Instant) -> Self {
+        return Self {
+            sim_executions_per_second: sim_executions_per_second as u64,
+            accumulated_time: 0,
+            last_update: now,
+            slow_down: false,
+        };
+    }
+
+    /// Set whether frames should be lengthened slightly, such as to let remote players catch up.
+    pub fn set_slow_down(&mut self, slow_down: bool) {
+        self.slow_down = slow_down;
+    }
+
+    /// Accumulate the time elapsed since the last update.
+    pub fn update(&mut self, now: Instant) {
+        self.accumulated_time = self.accumulated_time_at(now);
+        self.last_update = now;
+
+        let max_accumulated_time = self.frame_cost() * MAX_CATCH_UP_FRAMES;
+        if self.accumulated_time > max_accumulated_time {
+            self.accumulated_time = max_accumulated_time;
+        }
+    }
+
+    /// Drop all accumulated time, starting over from the given time.
+    pub fn reset(&mut self, now: Instant) {
+        self.accumulated_time = 0;
+        self.last_update = now;
+    }
+
+    /// Whether enough time has accumulated to run a frame.
+    pub fn is_frame_ready(&self, now: Instant) -> bool {
+        return self.accumulated_time_at(now) >= self.frame_cost();
+    }
+
+    /// Spend the time of a single frame. Must only be called when a frame is ready.
+    pub fn consume_frame(&mut self) {
+        self.accumulated_time -= self.frame_cost();
+    }
+
+    /// Get how far the accumulated time is into the next frame, from 0 to 1. Used by renderers to interpolate between frames.
+    pub fn alpha(&self, now: Instant) -> f32 {
+        let alpha = self.accumulated_time_at(now) as f64 / self.frame_cost() as f64;
+
+        return alpha.min(1.0) as f32;
+    }
+
+    /// Get the cost of a single frame, in nanoseconds multiplied by the frame rate.
+    fn frame_cost(&self) -> u64 {
+        if self.slow_down {
+            return NANOSECONDS_IN_SECOND + NANOSECONDS_IN_SECOND / SLOW_DOWN_FRAME_DIVISOR;
+        }
+
+        return NANOSECONDS_IN_SECOND;
+    }
+
+    /// Get the accumulated time, including the time elapsed since the last update.
+    fn accumulated_time_at(&self, now: Instant) -> u64 {
+        let elapsed_nanoseconds = (now - self.last_update).whole_nanoseconds().max(0) as u64;
+        let elapsed_time = elapsed_nanoseconds.saturating_mul(self.sim_executions_per_second);
+
+        return self.accumulated_time.saturating_add(elapsed_time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Duration;
+
+    fn run_ready_frames(scheduler: &mut RFrameScheduler, now: Instant) -> usize {
+        scheduler.update(now);
+
+        let mut frames = 0;
+        while scheduler.is_frame_ready(now) {
+            scheduler.consume_frame();
+            frames += 1;
+        }
+
+        return frames;
+    }
+
+    #[test]
+    fn rframe_scheduler_is_frame_ready_no_time_elapsed_returns_false() {
+        let start = Instant::now();
+        let scheduler = RFrameScheduler::new(60, start);
+
+        assert_eq!(false, scheduler.is_frame_ready(start));
+    }
+
+    #[test]
+    fn rframe_scheduler_is_frame_ready_frame_elapsed_returns_true() {
+        let start = Instant::now();
+        let scheduler = RFrameScheduler::new(60, start);
+
+        assert_eq!(
+            false,
+            scheduler.is_frame_ready(start + Duration::microseconds(16_666))
+        );
+        assert_eq!(
+            true,
+            scheduler.is_frame_ready(start + Duration::microseconds(16_667))
+        );
+    }
+
+    #[test]
+    fn rframe_scheduler_fractional_frame_durations_do_not_drift() {
+        let start = Instant::now();
+        let mut scheduler = RFrameScheduler::new(60, start);
+
+        let mut frames = 0;
+        for millisecond in 1..=1000 {
+            frames += run_ready_frames(&mut scheduler, start + Duration::milliseconds(millisecond));
+        }
+
+        assert_eq!(60, frames);
+    }
+
+    #[test]
+    fn rframe_scheduler_update_after_hitch_catches_up_bounded_frames() {
+        let start = Instant::now();
+        let mut scheduler = RFrameScheduler::new(60, start);
+
+        let actual = run_ready_frames(&mut scheduler, start + Duration::seconds(10));
+
+        assert_eq!(MAX_CATCH_UP_FRAMES as usize, actual);
+    }
+
+    #[test]
+    fn rframe_scheduler_reset_drops_accumulated_time() {
+        let start = Instant::now();
+        let mut scheduler = RFrameScheduler::new(60, start);
+        let now = start + Duration::seconds(1);
+
+        scheduler.reset(now);
+
+        assert_eq!(false, scheduler.is_frame_ready(now));
+    }
+
+    #[test]
+    fn rframe_scheduler_slow_down_lengthens_frames() {
+        let start = Instant::now();
+        let mut scheduler = RFrameScheduler::new(8, start);
+        scheduler.set_slow_down(true);
+
+        assert_eq!(
+            false,
+            scheduler.is_frame_ready(start + Duration::milliseconds(125))
+        );
+        assert_eq!(
+            true,
+            scheduler.is_frame_ready(start + Duration::milliseconds(141))
+        );
+    }
+
+    #[test]
+    fn rframe_scheduler_alpha_half_frame_returns_half() {
+        let start = Instant::now();
+        let mut scheduler = RFrameScheduler::new(10, start);
+
+        run_ready_frames(&mut scheduler, start + Duration::milliseconds(150));
+
+        assert_eq!(0.5, scheduler.alpha(start + Duration::milliseconds(150)));
+    }
+}
@@ -0,0 +1,99 @@
+/// A fixed-size ring of game states keyed by the frame they were saved at. Saving a state overwrites the one saved capacity frames before it.
+pub struct RStateBuffer<TGameState>
+where
+    TGameState: Copy,
+{
+    states: Vec<Option<RSavedState<TGameState>>>,
+}
+
+#[derive(Copy, Clone)]
+struct RSavedState<TGameState>
+where
+    TGameState: Copy,
+{
+    frame: usize,
+    game_state: TGameState,
+}
+
+impl<TGameState> RStateBuffer<TGameState>
+where
+    TGameState: Copy,
+{
+    /// Initialize a new buffer that holds the states of the given number of frames.
+    pub fn new(capacity: usize) -> Self {
+        let mut states = vec![];
+
+        for _i in 0..capacity {
+            states.push(None);
+        }
+
+        return Self { states: states };
+    }
+
+    /// Save the game state at the start of the given frame.
+    pub fn save(&mut self, frame: usize, game_state: TGameState) {
+        let index = frame % self.states.len();
+
+        self.states[index] = Some(RSavedState {
+            frame: frame,
+            game_state: game_state,
+        });
+    }
+
+    /// Load the game state at the start of the given frame. Returns None if the frame was never saved or has been overwritten.
+    pub fn load(&self, frame: usize) -> Option<TGameState> {
+        let index = frame % self.states.len();
+
+        return match self.states[index] {
+            Some(saved_state) if saved_state.frame == frame => Some(saved_state.game_state),
+            _ => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rstate_buffer_load_saved_frame_returns_state() {
+        let mut buffer = RStateBuffer::<i32>::new(4);
+        buffer.save(0, 10);
+        buffer.save(1, 11);
+        buffer.save(2, 12);
+
+        assert_eq!(Some(10), buffer.load(0));
+        assert_eq!(Some(11), buffer.load(1));
+        assert_eq!(Some(12), buffer.load(2));
+    }
+
+    #[test]
+    fn rstate_buffer_load_unsaved_frame_returns_none() {
+        let mut buffer = RStateBuffer::<i32>::new(4);
+        buffer.save(0, 10);
+
+        assert_eq!(None, buffer.load(1));
+        assert_eq!(None, buffer.load(4));
+    }
+
+    #[test]
+    fn rstate_buffer_load_overwritten_frame_returns_none() {
+        let mut buffer = RStateBuffer::<i32>::new(4);
+        for frame in 0..6 {
+            buffer.save(frame, frame as i32 + 10);
+        }
+
+        assert_eq!(None, buffer.load(1));
+        assert_eq!(Some(12), buffer.load(2));
+        assert_eq!(Some(15), buffer.load(5));
+    }
+
+    #[test]
+    fn rstate_buffer_save_same_frame_replaces_state() {
+        let mut buffer = RStateBuffer::<i32>::new(4);
+        buffer.save(3, 1);
+        buffer.save(3, 2);
+
+        assert_eq!(Some(2), buffer.load(3));
+    }
+}
@@ -0,0 +1,520 @@
+use super::*;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use time::{Duration, Instant};
+
+/// The number of round trips to complete with a peer before the session can start.
+const NUM_SYNC_ROUND_TRIPS: usize = 5;
+
+/// How long to wait for a reply before sending another sync request.
+const SYNC_RETRY_INTERVAL_MILLISECONDS: i64 = 200;
+
+/// The settings every peer in a session must agree on before the session can start.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RMercurySessionConfig {
+    /// The number of players in the session
+    pub number_of_players: usize,
+    /// The number of times the game advances per second
+    pub sim_executions_per_second: usize,
+    /// The number of frames between the checksums compared with the remote peers, or 0 if disabled
+    pub desync_detection_interval: usize,
+}
+
+/// The player id a local player claims during the handshake. Players without an explicit id are assigned one using their random seed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RPlayerClaim {
+    /// The player id assigned through the builder, if any
+    pub player_id: Option<usize>,
+    /// The random seed used to order peers without an explicit player id
+    pub seed: u32,
+}
+
+impl RPlayerClaim {
+    pub fn new(player_id: Option<usize>, seed: u32) -> Self {
+        return Self {
+            player_id: player_id,
+            seed: seed,
+        };
+    }
+}
+
+/// Generate a random seed to negotiate the local player id with.
+pub fn random_seed() -> u32 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(0);
+
+    return hasher.finish() as u32;
+}
+
+/// Assign a unique player id to every claim. Explicit ids are kept, while the remaining claims take the lowest free ids in the order of their seeds. Every peer assigns the same ids as long as it sees the same claims.
+pub fn assign_player_ids(claims: &[RPlayerClaim]) -> Vec<usize> {
+    let mut player_ids: Vec<usize> = claims
+        .iter()
+        .map(|claim| claim.player_id.unwrap_or(0))
+        .collect();
+
+    let mut unassigned: Vec<usize> = (0..claims.len())
+        .filter(|i| claims[*i].player_id.is_none())
+        .collect();
+    unassigned.sort_by_key(|i| claims[*i].seed);
+
+    let mut next_player_id = 1;
+    for i in unassigned {
+        while claims
+            .iter()
+            .any(|claim| claim.player_id == Some(next_player_id))
+        {
+            next_player_id += 1;
+        }
+
+        player_ids[i] = next_player_id;
+        next_player_id += 1;
+    }
+
+    return player_ids;
+}
+
+/// Check that the claims of every player in the session fit in it. Fails if an explicit id is claimed twice, or an explicit or negotiated id is past the number of players.
+pub fn check_player_claims(
+    claims: &[RPlayerClaim],
+    number_of_players: usize,
+) -> Result<(), RMercuryError> {
+    // Player ids start at 1, with 0 meaning the id is negotiated.
+    let explicit_player_ids: Vec<usize> =
+        claims.iter().filter_map(|claim| claim.player_id).collect();
+    for (i, player_id) in explicit_player_ids.iter().enumerate() {
+        if *player_id > number_of_players {
+            return Err(RMercuryError::InvalidPlayerId(*player_id));
+        }
+
+        if explicit_player_ids[..i].contains(player_id) {
+            return Err(RMercuryError::DuplicatePlayerId(*player_id));
+        }
+    }
+
+    for player_id in assign_player_ids(claims) {
+        if player_id > number_of_players {
+            return Err(RMercuryError::InvalidPlayerId(player_id));
+        }
+    }
+
+    return Ok(());
+}
+
+/// The state of the handshake with a single peer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RSyncState {
+    /// Still exchanging sync requests with the peer.
+    Synchronizing,
+    /// The handshake completed and the session may start.
+    Synchronized,
+    /// The handshake failed and the session can not start.
+    Failed(RMercuryError),
+}
+
+/// A sync request sent to the peer that is waiting for its reply.
+struct RPendingRequest {
+    nonce: u32,
+    sent_at: Instant,
+}
+
+/// Runs the handshake with a single peer. Sync requests carrying random nonces are sent until enough of them have been echoed back by the peer, measuring the round trip time along the way.
+pub struct RSynchronizer {
+    config: RMercurySessionConfig,
+    local_claims: Vec<RPlayerClaim>,
+    expected_remote_player_id: Option<usize>,
+    remote_claims: Option<Vec<RPlayerClaim>>,
+    state: RSyncState,
+    round_trips_remaining: usize,
+    pending_request: Option<RPendingRequest>,
+    random_state: RandomState,
+    nonce_counter: u64,
+    round_trip_time_total: Duration,
+    round_trip_samples: i32,
+}
+
+impl RSynchronizer {
+    /// Create a new synchronizer that will agree on the given config with the peer. If one of the peer's player ids is known, the handshake fails when the peer does not claim it.
+    pub fn new(
+        config: RMercurySessionConfig,
+        local_claims: Vec<RPlayerClaim>,
+        expected_remote_player_id: Option<usize>,
+    ) -> Self {
+        return Self {
+            config: config,
+            local_claims: local_claims,
+            expected_remote_player_id: expected_remote_player_id,
+            remote_claims: None,
+            state: RSyncState::Synchronizing,
+            round_trips_remaining: NUM_SYNC_ROUND_TRIPS,
+            pending_request: None,
+            random_state: RandomState::new(),
+            nonce_counter: 0,
+            round_trip_time_total: Duration::zero(),
+            round_trip_samples: 0,
+        };
+    }
+
+    /// Get the local session's config.
+    pub fn config(&self) -> RMercurySessionConfig {
+        return self.config;
+    }
+
+    /// Get the player ids the local players claim.
+    pub fn local_claims(&self) -> &Vec<RPlayerClaim> {
+        return &self.local_claims;
+    }
+
+    /// Get the player ids the peer's local players claimed, once they have been received.
+    pub fn remote_claims(&self) -> Option<&Vec<RPlayerClaim>> {
+        return self.remote_claims.as_ref();
+    }
+
+    /// Get the state of the handshake.
+    pub fn state(&self) -> RSyncState {
+        return self.state;
+    }
+
+    /// Get the number of completed round trips, along with the number needed to synchronize.
+    pub fn progress(&self) -> (usize, usize) {
+        return (
+            NUM_SYNC_ROUND_TRIPS - self.round_trips_remaining,
+            NUM_SYNC_ROUND_TRIPS,
+        );
+    }
+
+    /// Get the average round trip time measured during the handshake.
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        if self.round_trip_samples == 0 {
+            return None;
+        }
+
+        return Some(self.round_trip_time_total / self.round_trip_samples);
+    }
+
+    /// Get the nonce of a sync request to send, if one is due.
+    pub fn poll_request(&mut self, now: Instant) -> Option<u32> {
+        if self.state != RSyncState::Synchronizing {
+            return None;
+        }
+
+        let request_due = match &self.pending_request {
+            Some(pending_request) => {
+                now - pending_request.sent_at
+                    >= Duration::milliseconds(SYNC_RETRY_INTERVAL_MILLISECONDS)
+            }
+            None => true,
+        };
+
+        if !request_due {
+            return None;
+        }
+
+        let nonce = self.next_nonce();
+        self.pending_request = Some(RPendingRequest {
+            nonce: nonce,
+            sent_at: now,
+        });
+
+        return Some(nonce);
+    }
+
+    /// Handle a sync request from the peer. The request is answered regardless of the local state, as the peer may still be synchronizing.
+    pub fn receive_request(
+        &mut self,
+        remote_config: RMercurySessionConfig,
+        remote_claims: Vec<RPlayerClaim>,
+    ) {
+        self.check_remote(remote_config, remote_claims);
+    }
+
+    /// Handle a sync reply from the peer. Replies that don't match the pending request are ignored.
+    pub fn receive_reply(
+        &mut self,
+        nonce: u32,
+        remote_config: RMercurySessionConfig,
+        remote_claims: Vec<RPlayerClaim>,
+        now: Instant,
+    ) {
+        self.check_remote(remote_config, remote_claims);
+
+        if self.state != RSyncState::Synchronizing {
+            return;
+        }
+
+        let sent_at = match &self.pending_request {
+            Some(pending_request) if pending_request.nonce == nonce => pending_request.sent_at,
+            _ => return,
+        };
+
+        self.pending_request = None;
+        self.round_trip_time_total += now - sent_at;
+        self.round_trip_samples += 1;
+
+        self.round_trips_remaining -= 1;
+        if self.round_trips_remaining == 0 {
+            self.state = RSyncState::Synchronized;
+        }
+    }
+
+    fn check_remote(
+        &mut self,
+        remote_config: RMercurySessionConfig,
+        remote_claims: Vec<RPlayerClaim>,
+    ) {
+        let remote_player_ids: Vec<usize> = remote_claims
+            .iter()
+            .filter_map(|claim| claim.player_id)
+            .collect();
+        let mut claims = self.local_claims.clone();
+        claims.extend(remote_claims.iter());
+        self.remote_claims = Some(remote_claims);
+
+        if remote_config != self.config {
+            self.state = RSyncState::Failed(RMercuryError::ConfigMismatch {
+                local: self.config,
+                remote: remote_config,
+            });
+            return;
+        }
+
+        // Claims of other peers are checked once the handshakes with all of them have completed.
+        if let Err(error) = check_player_claims(&claims, self.config.number_of_players) {
+            self.state = RSyncState::Failed(error);
+            return;
+        }
+
+        if let Some(expected) = self.expected_remote_player_id {
+            if !remote_player_ids.contains(&expected) {
+                self.state = RSyncState::Failed(RMercuryError::PlayerIdMismatch {
+                    expected: expected,
+                    actual: remote_player_ids.first().copied(),
+                });
+            }
+        }
+    }
+
+    fn next_nonce(&mut self) -> u32 {
+        self.nonce_counter += 1;
+
+        let mut hasher = self.random_state.build_hasher();
+        hasher.write_u64(self.nonce_counter);
+
+        return hasher.finish() as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmercury_test_game::{test_claims, test_config};
+
+    #[test]
+    fn rsynchronizer_receive_reply_all_round_trips_synchronizes() {
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), None);
+        let now = Instant::now();
+
+        for round_trip in 0..NUM_SYNC_ROUND_TRIPS {
+            assert_eq!(RSyncState::Synchronizing, synchronizer.state());
+
+            let nonce = synchronizer.poll_request(now).unwrap();
+            let reply_at = now + Duration::milliseconds(round_trip as i64 * 10);
+            synchronizer.receive_reply(nonce, test_config(), test_claims(), reply_at);
+        }
+
+        assert_eq!(RSyncState::Synchronized, synchronizer.state());
+        assert_eq!(
+            (NUM_SYNC_ROUND_TRIPS, NUM_SYNC_ROUND_TRIPS),
+            synchronizer.progress()
+        );
+        assert_eq!(
+            Some(Duration::milliseconds(20)),
+            synchronizer.round_trip_time()
+        );
+        assert_eq!(None, synchronizer.poll_request(now));
+    }
+
+    #[test]
+    fn rsynchronizer_receive_reply_wrong_nonce_is_ignored() {
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), None);
+        let now = Instant::now();
+
+        let nonce = synchronizer.poll_request(now).unwrap();
+        synchronizer.receive_reply(nonce.wrapping_add(1), test_config(), test_claims(), now);
+
+        assert_eq!(None, synchronizer.round_trip_time());
+        assert_eq!(None, synchronizer.poll_request(now));
+    }
+
+    #[test]
+    fn rsynchronizer_poll_request_no_reply_retries() {
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), None);
+        let now = Instant::now();
+
+        let first_nonce = synchronizer.poll_request(now).unwrap();
+        let retry_at = now + Duration::milliseconds(SYNC_RETRY_INTERVAL_MILLISECONDS);
+        let second_nonce = synchronizer.poll_request(retry_at).unwrap();
+
+        assert_ne!(first_nonce, second_nonce);
+    }
+
+    #[test]
+    fn rsynchronizer_receive_request_other_config_fails() {
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), None);
+        let mut remote_config = test_config();
+        remote_config.sim_executions_per_second = 30;
+
+        synchronizer.receive_request(remote_config, test_claims());
+
+        assert_eq!(
+            RSyncState::Failed(RMercuryError::ConfigMismatch {
+                local: test_config(),
+                remote: remote_config
+            }),
+            synchronizer.state()
+        );
+    }
+
+    #[test]
+    fn rsynchronizer_receive_request_other_desync_detection_interval_fails() {
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), None);
+        let mut remote_config = test_config();
+        remote_config.desync_detection_interval = 10;
+
+        synchronizer.receive_request(remote_config, test_claims());
+
+        assert_eq!(
+            RSyncState::Failed(RMercuryError::ConfigMismatch {
+                local: test_config(),
+                remote: remote_config
+            }),
+            synchronizer.state()
+        );
+    }
+
+    #[test]
+    fn rsynchronizer_receive_request_other_player_id_fails() {
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), Some(2));
+
+        synchronizer.receive_request(test_config(), vec![RPlayerClaim::new(Some(1), 0)]);
+
+        assert_eq!(
+            RSyncState::Failed(RMercuryError::PlayerIdMismatch {
+                expected: 2,
+                actual: Some(1)
+            }),
+            synchronizer.state()
+        );
+    }
+
+    #[test]
+    fn rsynchronizer_receive_request_player_id_past_number_of_players_fails() {
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), None);
+
+        synchronizer.receive_request(test_config(), vec![RPlayerClaim::new(Some(3), 0)]);
+
+        assert_eq!(
+            RSyncState::Failed(RMercuryError::InvalidPlayerId(3)),
+            synchronizer.state()
+        );
+    }
+
+    #[test]
+    fn rsynchronizer_receive_request_local_player_id_past_number_of_players_fails() {
+        let local_claims = vec![RPlayerClaim::new(Some(5), 0)];
+        let mut synchronizer = RSynchronizer::new(test_config(), local_claims, None);
+
+        synchronizer.receive_request(test_config(), test_claims());
+
+        assert_eq!(
+            RSyncState::Failed(RMercuryError::InvalidPlayerId(5)),
+            synchronizer.state()
+        );
+    }
+
+    #[test]
+    fn rsynchronizer_receive_request_same_player_id_fails() {
+        let local_claims = vec![RPlayerClaim::new(None, 0), RPlayerClaim::new(Some(1), 0)];
+        let mut synchronizer = RSynchronizer::new(test_config(), local_claims, None);
+
+        synchronizer.receive_request(test_config(), vec![RPlayerClaim::new(Some(1), 0)]);
+
+        assert_eq!(
+            RSyncState::Failed(RMercuryError::DuplicatePlayerId(1)),
+            synchronizer.state()
+        );
+    }
+
+    #[test]
+    fn rsynchronizer_receive_request_more_claims_than_players_fails() {
+        let remote_claims = vec![RPlayerClaim::new(None, 1), RPlayerClaim::new(None, 2)];
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), None);
+
+        synchronizer.receive_request(test_config(), remote_claims);
+
+        assert_eq!(
+            RSyncState::Failed(RMercuryError::InvalidPlayerId(3)),
+            synchronizer.state()
+        );
+    }
+
+    #[test]
+    fn rsynchronizer_receive_request_remote_player_id_twice_fails() {
+        let remote_claims = vec![RPlayerClaim::new(Some(2), 0), RPlayerClaim::new(Some(2), 0)];
+        let mut synchronizer = RSynchronizer::new(test_config(), test_claims(), None);
+
+        synchronizer.receive_request(test_config(), remote_claims);
+
+        assert_eq!(
+            RSyncState::Failed(RMercuryError::DuplicatePlayerId(2)),
+            synchronizer.state()
+        );
+    }
+
+    #[test]
+    fn check_player_claims_negotiated_ids_fit_returns_ok() {
+        let claims = vec![
+            RPlayerClaim::new(None, 10),
+            RPlayerClaim::new(Some(1), 0),
+            RPlayerClaim::new(None, 20),
+        ];
+
+        assert_eq!(Ok(()), check_player_claims(&claims, 3));
+    }
+
+    #[test]
+    fn check_player_claims_negotiated_id_past_number_of_players_fails() {
+        let claims = vec![
+            RPlayerClaim::new(None, 10),
+            RPlayerClaim::new(Some(2), 0),
+            RPlayerClaim::new(None, 20),
+        ];
+
+        assert_eq!(
+            Err(RMercuryError::InvalidPlayerId(3)),
+            check_player_claims(&claims, 2)
+        );
+    }
+
+    #[test]
+    fn assign_player_ids_unassigned_claims_ordered_by_seed() {
+        let claims = vec![
+            RPlayerClaim::new(None, 30),
+            RPlayerClaim::new(None, 10),
+            RPlayerClaim::new(None, 20),
+        ];
+
+        assert_eq!(vec![3, 1, 2], assign_player_ids(&claims));
+    }
+
+    #[test]
+    fn assign_player_ids_explicit_ids_are_skipped() {
+        let claims = vec![
+            RPlayerClaim::new(None, 30),
+            RPlayerClaim::new(Some(1), 0),
+            RPlayerClaim::new(None, 10),
+        ];
+
+        assert_eq!(vec![3, 1, 2], assign_player_ids(&claims));
+    }
+}
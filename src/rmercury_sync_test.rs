@@ -0,0 +1,240 @@
+/// A frame whose game state differed after being rolled back and simulated again, meaning the game simulation is not deterministic.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RMercurySyncTestMismatch {
+    /// The frame that was simulated
+    pub frame: usize,
+    /// The logged game state after first simulating the frame
+    pub expected: String,
+    /// The logged game state after simulating the frame again
+    pub actual: String,
+}
+
+/// Remembers the checksum and game state after each recent frame, so a resimulated frame can be checked against it.
+pub struct RSyncTest<TGameState>
+where
+    TGameState: Copy,
+{
+    check_distance: usize,
+    records: Vec<RSyncTestRecord<TGameState>>,
+}
+
+struct RSyncTestRecord<TGameState>
+where
+    TGameState: Copy,
+{
+    frame: usize,
+    checksum: u64,
+    game_state: TGameState,
+}
+
+impl<TGameState> RSyncTest<TGameState>
+where
+    TGameState: Copy,
+{
+    /// Initialize a new sync test that rolls back the given number of frames.
+    pub fn new(check_distance: usize) -> Self {
+        return Self {
+            check_distance: check_distance,
+            records: vec![],
+        };
+    }
+
+    /// The number of frames rolled back after each frame.
+    pub fn check_distance(&self) -> usize {
+        return self.check_distance;
+    }
+
+    /// Record the checksum and game state after first simulating the given frame. Only the frames within the check distance are kept.
+    pub fn record(&mut self, frame: usize, checksum: u64, game_state: TGameState) {
+        self.records.retain(|record| record.frame < frame);
+        self.records.push(RSyncTestRecord {
+            frame: frame,
+            checksum: checksum,
+            game_state: game_state,
+        });
+
+        if self.records.len() > self.check_distance {
+            let excess = self.records.len() - self.check_distance;
+            self.records.drain(..excess);
+        }
+    }
+
+    /// Check the checksum after simulating the given frame again. Returns the recorded game state if the checksum differs, or None if it matches or was never recorded.
+    pub fn check(&self, frame: usize, checksum: u64) -> Option<TGameState> {
+        let record = self.records.iter().find(|record| record.frame == frame)?;
+
+        if record.checksum == checksum {
+            return None;
+        }
+
+        return Some(record.game_state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rmercury_test_game::{TestGame, TestInput, TestState};
+    use crate::*;
+
+    /// A game that also folds in how often it was advanced, which differs once frames are simulated again.
+    struct NonDeterministicGame {
+        game: TestGame,
+        advance_count: u64,
+        /// Whether the checksum ignores the game state, hiding the non-determinism.
+        constant_checksum: bool,
+    }
+
+    impl RMercuryGameInterface<TestState, TestInput> for NonDeterministicGame {
+        fn load_game_state(&mut self, game_state: TestState) {
+            self.game.load_game_state(game_state);
+        }
+
+        fn log_game_state(&self) -> String {
+            return self.game.log_game_state();
+        }
+
+        fn advance_frame(&mut self, inputs: Vec<TestInput>) {
+            self.game.advance_frame(inputs);
+
+            self.advance_count += 1;
+            self.game.state.total += self.advance_count;
+        }
+
+        fn current_game_state(&self) -> TestState {
+            return self.game.current_game_state();
+        }
+
+        fn checksum(&self) -> u64 {
+            if self.constant_checksum {
+                return 0;
+            }
+
+            return self.game.checksum();
+        }
+    }
+
+    #[test]
+    fn rsync_test_check_same_checksum_returns_none() {
+        let mut sync_test = RSyncTest::new(2);
+        sync_test.record(0, 100, 10);
+
+        assert_eq!(None, sync_test.check(0, 100));
+    }
+
+    #[test]
+    fn rsync_test_check_other_checksum_returns_recorded_state() {
+        let mut sync_test = RSyncTest::new(2);
+        sync_test.record(0, 100, 10);
+        sync_test.record(1, 101, 11);
+
+        assert_eq!(Some(11), sync_test.check(1, 102));
+    }
+
+    #[test]
+    fn rsync_test_check_frame_beyond_distance_returns_none() {
+        let mut sync_test = RSyncTest::new(2);
+        sync_test.record(0, 100, 10);
+        sync_test.record(1, 101, 11);
+        sync_test.record(2, 102, 12);
+
+        assert_eq!(None, sync_test.check(0, 0));
+        assert_eq!(Some(11), sync_test.check(1, 0));
+    }
+
+    #[test]
+    fn rmercury_sync_test_deterministic_game_runs() {
+        let mut game = TestGame::new();
+        let mut builder = RMercuryBuilder::new(&mut game)
+            .with_type(MercuryType::SyncTest)
+            .with_sync_test_frames(4)
+            .with_local_input_delay(0)
+            .with_clock(RMercuryManualClock::new());
+
+        let mut session = builder.build();
+        for frame in 0..30 {
+            session.add_local_input(0, &mut vec![TestInput::new(0, frame as u8)]);
+            assert_eq!(RMercuryExecutionResults::Executed, session.advance_frame());
+        }
+
+        assert_eq!(30, session.get_current_tick());
+        assert_eq!(None, session.get_sync_test_mismatch());
+    }
+
+    #[test]
+    fn rmercury_sync_test_non_deterministic_game_reports_mismatch() {
+        let mut game = NonDeterministicGame {
+            game: TestGame::new(),
+            advance_count: 0,
+            constant_checksum: false,
+        };
+        let mut builder = RMercuryBuilder::new(&mut game)
+            .with_type(MercuryType::SyncTest)
+            .with_clock(RMercuryManualClock::new());
+
+        let mut session = builder.build();
+        let results = session.advance_frame();
+
+        let expected = RMercurySyncTestMismatch {
+            frame: 0,
+            expected: String::from("frame: 1, total: 1"),
+            actual: String::from("frame: 1, total: 2"),
+        };
+
+        assert_eq!(
+            RMercuryExecutionResults::Failed(RMercuryError::SyncTestMismatch { frame: 0 }),
+            results
+        );
+        assert_eq!(Some(&expected), session.get_sync_test_mismatch());
+        assert_eq!(results, session.advance_frame());
+    }
+
+    #[test]
+    fn rmercury_sync_test_overridden_checksum_is_compared() {
+        let mut game = NonDeterministicGame {
+            game: TestGame::new(),
+            advance_count: 0,
+            constant_checksum: true,
+        };
+        let mut builder = RMercuryBuilder::new(&mut game)
+            .with_type(MercuryType::SyncTest)
+            .with_clock(RMercuryManualClock::new());
+
+        let mut session = builder.build();
+
+        assert_eq!(10, session.step(10));
+        assert_eq!(None, session.get_sync_test_mismatch());
+    }
+
+    #[test]
+    fn rmercury_sync_test_mismatch_writes_diagnostics() {
+        let directory = std::env::temp_dir().join(format!(
+            "rmercury-sync-test-diagnostics-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let mut game = NonDeterministicGame {
+            game: TestGame::new(),
+            advance_count: 0,
+            constant_checksum: false,
+        };
+        let mut builder = RMercuryBuilder::new(&mut game)
+            .with_type(MercuryType::SyncTest)
+            .with_diagnostics_directory(directory.clone())
+            .with_clock(RMercuryManualClock::new());
+
+        let mut session = builder.build();
+        session.advance_frame();
+
+        let diagnostics = RMercuryDiagnostics::load(session.get_diagnostics_file().unwrap());
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let diagnostics = diagnostics.unwrap();
+        assert_eq!(0, diagnostics.detected_frame);
+        assert_eq!(
+            "SyncTest mismatch on frame 0\nExpected:\nframe: 1, total: 1\nActual:\nframe: 1, total: 2",
+            diagnostics.reason
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Shared fixtures for unit tests.
+use super::*;
+use rmercury_channel::{RChannel, RChannelManager};
+use rmercury_prediction::RMercuryRepeatLastInput;
+use rmercury_sync::RPlayerClaim;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use time::Duration;
+
+pub type TestSession<'a> = RMercury<'a, TestGame, TestInput, TestState>;
+pub type TestBuilder<'a> = RMercuryBuilder<'a, TestGame, TestInput, TestState>;
+
+/// A minimal input that carries a single value for a player.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TestInput {
+    pub player_id: usize,
+    pub value: u8,
+}
+
+impl TestInput {
+    pub fn new(player_id: usize, value: u8) -> Self {
+        return Self {
+            player_id: player_id,
+            value: value,
+        };
+    }
+}
+
+impl RMercuryInput for TestInput {
+    fn get_player_id(&self) -> usize {
+        return self.player_id;
+    }
+
+    fn set_player_id(&mut self, player_id: usize) {
+        self.player_id = player_id;
+    }
+
+    fn to_bits(&self) -> Vec<u8> {
+        return vec![self.value];
+    }
+
+    fn from_bits(bytes: Vec<u8>) -> Result<Self, RMercuryError> {
+        return match bytes.as_slice() {
+            [value] => Ok(Self::new(0, *value)),
+            _ => Err(RMercuryError::InputMalformed),
+        };
+    }
+}
+
+/// The state of the test game. Each frame folds the received inputs into a running total, and the order they were received in into another.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TestState {
+    pub frame: usize,
+    pub total: u64,
+    pub order: u64,
+}
+
+/// A minimal deterministic game used to drive RMercury sessions in tests.
+pub struct TestGame {
+    pub state: TestState,
+}
+
+impl TestGame {
+    pub fn new() -> Self {
+        return Self {
+            state: TestState {
+                frame: 0,
+                total: 0,
+                order: 0,
+            },
+        };
+    }
+
+    /// Create a game from a state serialized by `game_state_to_bits`.
+    pub fn from_bits(bits: &[u8]) -> Self {
+        let mut frame = [0; 4];
+        let mut total = [0; 8];
+        let mut order = [0; 8];
+        frame.copy_from_slice(&bits[0..4]);
+        total.copy_from_slice(&bits[4..12]);
+        order.copy_from_slice(&bits[12..20]);
+
+        return Self {
+            state: TestState {
+                frame: u32::from_le_bytes(frame) as usize,
+                total: u64::from_le_bytes(total),
+                order: u64::from_le_bytes(order),
+            },
+        };
+    }
+}
+
+impl RMercuryGameInterface<TestState, TestInput> for TestGame {
+    fn load_game_state(&mut self, game_state: TestState) {
+        self.state = game_state;
+    }
+
+    fn log_game_state(&self) -> String {
+        return format!("frame: {}, total: {}", self.state.frame, self.state.total);
+    }
+
+    fn advance_frame(&mut self, inputs: Vec<TestInput>) {
+        self.state.frame += 1;
+
+        for input in inputs.iter() {
+            self.state.total += (input.value as u64 + 1) * self.state.frame as u64;
+            self.state.order = self
+                .state
+                .order
+                .wrapping_mul(31)
+                .wrapping_add(input.player_id as u64);
+        }
+    }
+
+    fn current_game_state(&self) -> TestState {
+        return self.state;
+    }
+
+    fn disconnected_inputs(&self, player_id: usize) -> Vec<TestInput> {
+        return vec![TestInput::new(player_id, 0)];
+    }
+
+    fn game_state_to_bits(&self) -> Vec<u8> {
+        let mut bits = (self.state.frame as u32).to_le_bytes().to_vec();
+        bits.extend_from_slice(&self.state.total.to_le_bytes());
+        bits.extend_from_slice(&self.state.order.to_le_bytes());
+        return bits;
+    }
+
+    fn checksum(&self) -> u64 {
+        return fnv1a_hash(&self.game_state_to_bits());
+    }
+}
+
+/// A writer that appends to a buffer shared between its clones, so tests can inspect what a session wrote.
+#[derive(Clone)]
+pub struct TestWriter {
+    pub bits: Rc<RefCell<Vec<u8>>>,
+}
+
+impl TestWriter {
+    pub fn new() -> Self {
+        return Self {
+            bits: Rc::new(RefCell::new(vec![])),
+        };
+    }
+}
+
+impl Write for TestWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bits.borrow_mut().extend_from_slice(buf);
+        return Ok(buf.len());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+/// The session config of a session built with the default settings.
+pub fn test_config() -> RMercurySessionConfig {
+    return RMercurySessionConfig {
+        number_of_players: 2,
+        sim_executions_per_second: 60,
+        desync_detection_interval: 0,
+    };
+}
+
+/// The claims of a single local player whose id is negotiated.
+pub fn test_claims() -> Vec<RPlayerClaim> {
+    return vec![RPlayerClaim::new(None, 0)];
+}
+
+/// Build a channel manager with a single peer, connected over a memory transport to a channel acting as that peer. Neither has started the handshake.
+pub fn manager_pair(
+    session_id: u32,
+    config: RMercurySessionConfig,
+) -> (
+    RChannelManager<TestInput>,
+    RChannel<TestInput, RMercuryMemoryTransport>,
+) {
+    let (transport_a, transport_b) = RMercuryMemoryTransport::pair();
+    let manager = RChannelManager::new(
+        session_id,
+        vec![(None, Box::new(transport_a))],
+        Box::new(RMercuryRepeatLastInput),
+        config,
+        vec![None],
+    );
+    let remote = RChannel::new(transport_b, session_id, config, test_claims(), None);
+
+    return (manager, remote);
+}
+
+/// Build a pair of sessions connected over memory transports with the given latency. Both run a frame per millisecond of the clock, and are then configured by the closure, which is given the index of the session in the pair.
+pub fn session_pair<'a, F>(
+    clock: &RMercuryManualClock,
+    latency: Duration,
+    game_a: &'a mut TestGame,
+    game_b: &'a mut TestGame,
+    configure: F,
+) -> (TestSession<'a>, TestSession<'a>)
+where
+    F: Fn(usize, TestBuilder<'a>) -> TestBuilder<'a>,
+{
+    let (transport_a, transport_b) =
+        RMercuryMemoryTransport::pair_with_latency(clock.clone(), latency);
+
+    let mut builder_a = configure(0, test_builder(game_a, clock)).with_remote_peer(transport_a);
+    let mut builder_b = configure(1, test_builder(game_b, clock)).with_remote_peer(transport_b);
+
+    return (builder_a.build(), builder_b.build());
+}
+
+/// Build a pair of sessions like `session_pair`, and run them until the handshake between them completes.
+pub fn synchronized_pair<'a, F>(
+    clock: &RMercuryManualClock,
+    latency: Duration,
+    game_a: &'a mut TestGame,
+    game_b: &'a mut TestGame,
+    configure: F,
+) -> (TestSession<'a>, TestSession<'a>)
+where
+    F: Fn(usize, TestBuilder<'a>) -> TestBuilder<'a>,
+{
+    let (mut session_a, mut session_b) = session_pair(clock, latency, game_a, game_b, configure);
+    synchronize(clock, &mut [&mut session_a, &mut session_b]);
+
+    return (session_a, session_b);
+}
+
+/// Build a session on the game that runs a frame per millisecond of the clock.
+pub fn test_builder<'a>(game: &'a mut TestGame, clock: &RMercuryManualClock) -> TestBuilder<'a> {
+    return RMercuryBuilder::new(game)
+        .with_sim_executions_per_second(1000)
+        .with_clock(clock.clone());
+}
+
+/// Run the sessions a millisecond of the clock at a time, until the handshakes between them complete.
+pub fn synchronize(clock: &RMercuryManualClock, sessions: &mut [&mut TestSession]) {
+    for _ in 0..100 {
+        if sessions.iter().all(|session| session.is_synchronized()) {
+            return;
+        }
+
+        for session in sessions.iter_mut() {
+            session.execute();
+        }
+        clock.advance(Duration::milliseconds(1));
+    }
+
+    panic!("Sessions did not synchronize!");
+}
@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+
+/// The number of frames the frame advantage is averaged over.
+const FRAME_ADVANTAGE_WINDOW: usize = 40;
+
+/// The smallest advantage worth waiting for. Smaller differences are left alone to avoid constant adjustments.
+const MIN_FRAME_ADVANTAGE: i32 = 1;
+
+/// The largest number of frames to recommend waiting at once.
+const MAX_FRAME_ADVANTAGE: i32 = 9;
+
+/// Tracks how far the local session runs ahead of a remote peer, as seen from both sides.
+///
+/// Both sides see the other one lagging behind by the network latency, so the latency cancels out when the two views are compared. What remains is how far one clock runs ahead of the other.
+pub struct RTimeSync {
+    local_frame_advantages: VecDeque<i32>,
+    remote_frame_advantages: VecDeque<i32>,
+}
+
+impl RTimeSync {
+    pub fn new() -> Self {
+        return Self {
+            local_frame_advantages: VecDeque::with_capacity(FRAME_ADVANTAGE_WINDOW),
+            remote_frame_advantages: VecDeque::with_capacity(FRAME_ADVANTAGE_WINDOW),
+        };
+    }
+
+    /// Record the frame advantages for a frame. The local advantage is how far the local session is ahead of the peer, the remote advantage how far the peer reported being ahead of the local session.
+    pub fn advance_frame(&mut self, local_frame_advantage: i32, remote_frame_advantage: i32) {
+        if self.local_frame_advantages.len() == FRAME_ADVANTAGE_WINDOW {
+            self.local_frame_advantages.pop_front();
+            self.remote_frame_advantages.pop_front();
+        }
+
+        self.local_frame_advantages.push_back(local_frame_advantage);
+        self.remote_frame_advantages
+            .push_back(remote_frame_advantage);
+    }
+
+    /// Get the number of frames the local session should wait for the peer to catch up.
+    pub fn recommended_frame_delay(&self) -> usize {
+        // Missing samples count as no advantage, so a few early samples don't cause a large adjustment.
+        let samples = FRAME_ADVANTAGE_WINDOW as i32;
+        let local_average = self.local_frame_advantages.iter().sum::<i32>() / samples;
+        let remote_average = self.remote_frame_advantages.iter().sum::<i32>() / samples;
+
+        // Split the difference, as the peer will slow down by the same amount if it is ahead instead.
+        let frame_advantage = (local_average - remote_average) / 2;
+        if frame_advantage < MIN_FRAME_ADVANTAGE {
+            return 0;
+        }
+
+        return frame_advantage.min(MAX_FRAME_ADVANTAGE) as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rtime_sync_recommended_frame_delay_no_samples_returns_0() {
+        let time_sync = RTimeSync::new();
+
+        assert_eq!(0, time_sync.recommended_frame_delay());
+    }
+
+    #[test]
+    fn rtime_sync_recommended_frame_delay_few_samples_are_dampened() {
+        let mut time_sync = RTimeSync::new();
+        time_sync.advance_frame(4, 0);
+        time_sync.advance_frame(4, 0);
+
+        assert_eq!(0, time_sync.recommended_frame_delay());
+    }
+
+    #[test]
+    fn rtime_sync_recommended_frame_delay_equal_latency_returns_0() {
+        let mut time_sync = RTimeSync::new();
+        for _ in 0..FRAME_ADVANTAGE_WINDOW {
+            time_sync.advance_frame(3, 3);
+        }
+
+        assert_eq!(0, time_sync.recommended_frame_delay());
+    }
+
+    #[test]
+    fn rtime_sync_recommended_frame_delay_local_ahead_returns_half_difference() {
+        let mut time_sync = RTimeSync::new();
+        for _ in 0..FRAME_ADVANTAGE_WINDOW {
+            time_sync.advance_frame(6, -2);
+        }
+
+        assert_eq!(4, time_sync.recommended_frame_delay());
+    }
+
+    #[test]
+    fn rtime_sync_recommended_frame_delay_remote_ahead_returns_0() {
+        let mut time_sync = RTimeSync::new();
+        for _ in 0..FRAME_ADVANTAGE_WINDOW {
+            time_sync.advance_frame(-2, 6);
+        }
+
+        assert_eq!(0, time_sync.recommended_frame_delay());
+    }
+
+    #[test]
+    fn rtime_sync_recommended_frame_delay_is_capped() {
+        let mut time_sync = RTimeSync::new();
+        for _ in 0..FRAME_ADVANTAGE_WINDOW {
+            time_sync.advance_frame(100, -100);
+        }
+
+        assert_eq!(
+            MAX_FRAME_ADVANTAGE as usize,
+            time_sync.recommended_frame_delay()
+        );
+    }
+
+    #[test]
+    fn rtime_sync_advance_frame_old_samples_are_dropped() {
+        let mut time_sync = RTimeSync::new();
+        for _ in 0..FRAME_ADVANTAGE_WINDOW {
+            time_sync.advance_frame(8, 0);
+        }
+        for _ in 0..FRAME_ADVANTAGE_WINDOW {
+            time_sync.advance_frame(0, 0);
+        }
+
+        assert_eq!(0, time_sync.recommended_frame_delay());
+    }
+}
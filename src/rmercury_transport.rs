@@ -0,0 +1,218 @@
+use super::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::rc::Rc;
+use time::{Duration, Instant};
+
+/// The largest datagram the UDP transport will attempt to read.
+const MAX_DATAGRAM_SIZE: usize = 4096;
+
+/// The trait a transport must implement to move datagrams between the local session and a single remote peer. Delivery does not need to be reliable or ordered.
+pub trait RMercuryTransport {
+    /// Send a datagram to the remote peer. Failures are treated as dropped datagrams.
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()>;
+
+    /// Retrieve all datagrams received from the remote peer since the last poll. Must not block.
+    fn poll(&mut self) -> Vec<Vec<u8>>;
+}
+
+impl RMercuryTransport for Box<dyn RMercuryTransport> {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+        return (**self).send(datagram);
+    }
+
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        return (**self).poll();
+    }
+}
+
+/// Transport that exchanges datagrams with a remote peer over a non-blocking UDP socket.
+pub struct RMercuryUdpTransport {
+    socket: UdpSocket,
+}
+
+impl RMercuryUdpTransport {
+    /// Create a new transport over the given socket. The socket is connected to the remote address and set to non-blocking.
+    pub fn new(socket: UdpSocket, remote_address: SocketAddr) -> io::Result<Self> {
+        socket.connect(remote_address)?;
+        socket.set_nonblocking(true)?;
+
+        return Ok(Self { socket: socket });
+    }
+
+    /// Bind a socket to the local address and create a new transport to the remote address.
+    pub fn bind(local_address: SocketAddr, remote_address: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local_address)?;
+
+        return Self::new(socket, remote_address);
+    }
+}
+
+impl RMercuryTransport for RMercuryUdpTransport {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.socket.send(datagram)?;
+        return Ok(());
+    }
+
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        let mut datagrams = vec![];
+        let mut buffer = [0; MAX_DATAGRAM_SIZE];
+
+        // Read until the socket would block or errors out. Errors are retried on the next poll.
+        while let Ok(size) = self.socket.recv(&mut buffer) {
+            datagrams.push(buffer[..size].to_vec());
+        }
+
+        return datagrams;
+    }
+}
+
+/// A datagram passed through memory, along with when it may be received if the transport has a latency.
+struct RMemoryDatagram {
+    deliver_at: Option<Instant>,
+    datagram: Vec<u8>,
+}
+
+/// Transport that passes datagrams through shared memory. Useful for test harnesses or sessions that live in the same process. Datagrams can be held back until a clock has advanced past a latency, so sessions sharing a manual clock with it can be tested deterministically.
+pub struct RMercuryMemoryTransport {
+    outgoing: Rc<RefCell<VecDeque<RMemoryDatagram>>>,
+    incoming: Rc<RefCell<VecDeque<RMemoryDatagram>>>,
+    clock: Option<Rc<dyn RMercuryClock>>,
+    latency: Duration,
+}
+
+impl RMercuryMemoryTransport {
+    /// Create two transports that are connected to each other. Datagrams are received on the next poll.
+    pub fn pair() -> (Self, Self) {
+        return Self::connect(None, Duration::zero());
+    }
+
+    /// Create two transports that are connected to each other. Datagrams are received once the clock has advanced past the latency.
+    pub fn pair_with_latency<TClock>(clock: TClock, latency: Duration) -> (Self, Self)
+    where
+        TClock: RMercuryClock + 'static,
+    {
+        return Self::connect(Some(Rc::new(clock)), latency);
+    }
+
+    fn connect(clock: Option<Rc<dyn RMercuryClock>>, latency: Duration) -> (Self, Self) {
+        let a_to_b = Rc::new(RefCell::new(VecDeque::new()));
+        let b_to_a = Rc::new(RefCell::new(VecDeque::new()));
+
+        let a = Self {
+            outgoing: a_to_b.clone(),
+            incoming: b_to_a.clone(),
+            clock: clock.clone(),
+            latency: latency,
+        };
+
+        let b = Self {
+            outgoing: b_to_a,
+            incoming: a_to_b,
+            clock: clock,
+            latency: latency,
+        };
+
+        return (a, b);
+    }
+}
+
+impl RMercuryTransport for RMercuryMemoryTransport {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+        let deliver_at = self.clock.as_ref().map(|clock| clock.now() + self.latency);
+
+        self.outgoing.borrow_mut().push_back(RMemoryDatagram {
+            deliver_at: deliver_at,
+            datagram: datagram.to_vec(),
+        });
+        return Ok(());
+    }
+
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        let now = self.clock.as_ref().map(|clock| clock.now());
+        let mut incoming = self.incoming.borrow_mut();
+
+        // Both ends share the clock and latency, so datagrams become receivable in the order they were sent.
+        let mut datagrams = vec![];
+        while let Some(datagram) = incoming.front() {
+            let is_due = match (datagram.deliver_at, now) {
+                (Some(deliver_at), Some(now)) => deliver_at <= now,
+                _ => true,
+            };
+            if !is_due {
+                break;
+            }
+
+            datagrams.push(incoming.pop_front().unwrap().datagram);
+        }
+
+        return datagrams;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time};
+
+    #[test]
+    fn rmercury_memory_transport_send_delivers_to_other_end() {
+        let (mut a, mut b) = RMercuryMemoryTransport::pair();
+
+        a.send(&[1, 2, 3]).unwrap();
+        a.send(&[4]).unwrap();
+
+        let expected = vec![vec![1, 2, 3], vec![4]];
+        assert_eq!(expected, b.poll());
+        assert_eq!(true, a.poll().is_empty());
+        assert_eq!(true, b.poll().is_empty());
+    }
+
+    #[test]
+    fn rmercury_memory_transport_poll_with_latency_waits_for_clock() {
+        let clock = RMercuryManualClock::new();
+        let (mut a, mut b) =
+            RMercuryMemoryTransport::pair_with_latency(clock.clone(), Duration::milliseconds(2));
+
+        a.send(&[1]).unwrap();
+        assert_eq!(true, b.poll().is_empty());
+
+        clock.advance(Duration::milliseconds(1));
+        a.send(&[2]).unwrap();
+        assert_eq!(true, b.poll().is_empty());
+
+        clock.advance(Duration::milliseconds(1));
+        assert_eq!(vec![vec![1]], b.poll());
+
+        clock.advance(Duration::milliseconds(1));
+        assert_eq!(vec![vec![2]], b.poll());
+        assert_eq!(true, a.poll().is_empty());
+    }
+
+    #[test]
+    fn rmercury_udp_transport_send_delivers_to_other_end() {
+        let socket_a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket_b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address_a = socket_a.local_addr().unwrap();
+        let address_b = socket_b.local_addr().unwrap();
+
+        let mut a = RMercuryUdpTransport::new(socket_a, address_b).unwrap();
+        let mut b = RMercuryUdpTransport::new(socket_b, address_a).unwrap();
+
+        a.send(&[1, 2, 3]).unwrap();
+
+        let mut actual = vec![];
+        for _ in 0..100 {
+            actual = b.poll();
+            if !actual.is_empty() {
+                break;
+            }
+
+            thread::sleep(time::Duration::from_millis(1));
+        }
+
+        assert_eq!(vec![vec![1, 2, 3]], actual);
+    }
+}